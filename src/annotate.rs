@@ -0,0 +1,123 @@
+use crate::error::HgError;
+use crate::node::NodeId;
+use crate::revlog::Revision;
+use crate::revset::Revset;
+use crate::MercurialRepository;
+use serde::Deserialize;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AnnotatedLine {
+    pub rev: Revision,
+    pub node: NodeId,
+    pub author: String,
+    pub date: i64,
+    pub timezone: i32,
+    pub line_number_in_origin: usize,
+    pub path_in_origin: PathBuf,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnnotateOptions {
+    pub follow: bool,
+    pub ignore_all_space: bool,
+    pub ignore_space_change: bool,
+    pub ignore_blank_lines: bool,
+    pub ignore_space_at_eol: bool,
+}
+
+impl Default for AnnotateOptions {
+    fn default() -> Self {
+        AnnotateOptions {
+            follow: true,
+            ignore_all_space: false,
+            ignore_space_change: false,
+            ignore_blank_lines: false,
+            ignore_space_at_eol: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RawAnnotation {
+    #[serde(default)]
+    lines: Vec<RawLine>,
+}
+
+#[derive(Deserialize)]
+struct RawLine {
+    rev: Revision,
+    node: String,
+    user: String,
+    date: (f64, i32),
+    lineno: usize,
+    path: String,
+    line: String,
+}
+
+impl TryFrom<RawLine> for AnnotatedLine {
+    type Error = HgError;
+
+    fn try_from(raw: RawLine) -> Result<Self, Self::Error> {
+        let mut text = raw.line;
+        if text.ends_with('\n') {
+            text.pop();
+        }
+        Ok(AnnotatedLine {
+            rev: raw.rev,
+            node: raw.node.parse()?,
+            author: raw.user,
+            date: raw.date.0 as i64,
+            timezone: raw.date.1,
+            line_number_in_origin: raw.lineno,
+            path_in_origin: PathBuf::from(raw.path),
+            text,
+        })
+    }
+}
+
+impl MercurialRepository {
+    pub fn annotate(
+        &self,
+        path: &Path,
+        rev: Option<Revset>,
+        opts: &AnnotateOptions,
+    ) -> Result<Vec<AnnotatedLine>, HgError> {
+        let mut args: Vec<OsString> =
+            ["annotate", "-T", "json", "-u", "-n", "-c", "-d", "-f", "-l"]
+                .into_iter()
+                .map(OsString::from)
+                .collect();
+        if let Some(rev) = rev {
+            args.extend(["-r".into(), rev.into()]);
+        }
+        if !opts.follow {
+            args.push("--no-follow".into());
+        }
+        if opts.ignore_all_space {
+            args.push("-w".into());
+        }
+        if opts.ignore_space_change {
+            args.push("-b".into());
+        }
+        if opts.ignore_blank_lines {
+            args.push("-B".into());
+        }
+        if opts.ignore_space_at_eol {
+            args.push("-Z".into());
+        }
+        args.extend(["--".into(), self.path_arg(path)]);
+
+        let output = self.hg(&args)?;
+        let raw: Vec<RawAnnotation> =
+            serde_json::from_slice(&output).map_err(|e| HgError::Parse {
+                line: e.to_string(),
+            })?;
+        raw.into_iter()
+            .flat_map(|annotation| annotation.lines)
+            .map(AnnotatedLine::try_from)
+            .collect()
+    }
+}
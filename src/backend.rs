@@ -0,0 +1,218 @@
+use crate::cat::{native_cat, spawn_cat};
+use crate::changeset::{log_args, native_log, parse_json_changesets, Changeset, LogOptions};
+use crate::cmdserver::{spawn, CommandOutput, CommandServer, HgCommand};
+use crate::config::Config;
+use crate::diff::{diff_args, parse_git_diff, FileDiff};
+use crate::error::HgError;
+use crate::ignore::IgnoreMatcher;
+use crate::mercurial_file::MercurialFile;
+use crate::revset::Revset;
+use crate::split_rows;
+use crate::status::native_status_with;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Mutex;
+
+/// Executes the operations `MercurialRepository` needs. Only `run_command` is
+/// required; the typed operations default to running the equivalent hg
+/// command and parsing its output. Paths are relative to the repository root.
+pub trait HgBackend: fmt::Debug + Send + Sync {
+    fn run_command(&self, args: &[OsString]) -> Result<CommandOutput, HgError>;
+
+    fn path_arg(&self, path: &Path) -> OsString {
+        path.as_os_str().to_owned()
+    }
+
+    fn status(&self) -> Result<Vec<MercurialFile>, HgError> {
+        let args = ["status", "--all", "--copies"].map(OsString::from);
+        let output = checked(self.run_command(&args)?)?;
+        MercurialFile::parse_status(&split_rows(&output)?)
+    }
+
+    fn log(&self, opts: &LogOptions) -> Result<Vec<Changeset>, HgError> {
+        parse_json_changesets(&checked(
+            self.run_command(&log_args(opts, |path| self.path_arg(path)))?,
+        )?)
+    }
+
+    fn cat(&self, path: &Path, rev: Option<&Revset>) -> Result<Box<dyn Read + Send>, HgError> {
+        let mut args: Vec<OsString> = vec!["cat".into()];
+        if let Some(rev) = rev {
+            args.extend(["-r".into(), rev.clone().into()]);
+        }
+        args.extend(["--".into(), self.path_arg(path)]);
+        Ok(Box::new(Cursor::new(checked(self.run_command(&args)?)?)))
+    }
+
+    fn diff(
+        &self,
+        path: &Path,
+        from_rev: Option<&Revset>,
+        to_rev: Option<&Revset>,
+    ) -> Result<Vec<FileDiff>, HgError> {
+        let args = diff_args(self.path_arg(path), from_rev, to_rev);
+        parse_git_diff(&checked(self.run_command(&args)?)?)
+    }
+}
+
+pub(crate) fn checked(output: CommandOutput) -> Result<Vec<u8>, HgError> {
+    if output.code != 0 {
+        return Err(HgError::CommandFailed {
+            code: Some(output.code),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    Ok(output.stdout)
+}
+
+/// Keeps a single `hg serve --cmdserver pipe` process alive for the lifetime
+/// of the backend. File contents are still streamed from a separate process.
+#[derive(Debug)]
+pub struct CommandServerBackend {
+    command: HgCommand,
+    server: Mutex<CommandServer>,
+}
+
+impl CommandServerBackend {
+    pub fn new(repo_root: &Path) -> CommandServerBackend {
+        CommandServerBackend::with_command(HgCommand::new(repo_root))
+    }
+
+    pub(crate) fn with_command(command: HgCommand) -> CommandServerBackend {
+        CommandServerBackend {
+            server: Mutex::new(CommandServer::with_command(command.clone())),
+            command,
+        }
+    }
+}
+
+impl HgBackend for CommandServerBackend {
+    fn run_command(&self, args: &[OsString]) -> Result<CommandOutput, HgError> {
+        self.server
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .run_command(args)
+    }
+
+    fn path_arg(&self, path: &Path) -> OsString {
+        self.command.path_arg(path)
+    }
+
+    fn cat(&self, path: &Path, rev: Option<&Revset>) -> Result<Box<dyn Read + Send>, HgError> {
+        Ok(Box::new(spawn_cat(&self.command, path, rev)?))
+    }
+}
+
+/// Spawns a new hg process for every command.
+#[derive(Debug)]
+pub struct CliBackend {
+    command: HgCommand,
+}
+
+impl CliBackend {
+    pub fn new(repo_root: &Path) -> CliBackend {
+        CliBackend::with_command(HgCommand::new(repo_root))
+    }
+
+    pub(crate) fn with_command(command: HgCommand) -> CliBackend {
+        CliBackend { command }
+    }
+}
+
+impl HgBackend for CliBackend {
+    fn run_command(&self, args: &[OsString]) -> Result<CommandOutput, HgError> {
+        let child = spawn(
+            self.command
+                .command(args)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        )?;
+        let output = child.wait_with_output()?;
+        match output.status.code() {
+            Some(code) => Ok(CommandOutput {
+                stdout: output.stdout,
+                stderr: output.stderr,
+                code,
+            }),
+            None => Err(HgError::CommandFailed {
+                code: None,
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            }),
+        }
+    }
+
+    fn path_arg(&self, path: &Path) -> OsString {
+        self.command.path_arg(path)
+    }
+
+    fn cat(&self, path: &Path, rev: Option<&Revset>) -> Result<Box<dyn Read + Send>, HgError> {
+        Ok(Box::new(spawn_cat(&self.command, path, rev)?))
+    }
+}
+
+/// Reads the repository files directly without hg. Commands that have no
+/// native implementation fail with `HgError::Unsupported`.
+#[derive(Debug)]
+pub struct NativeBackend {
+    root: PathBuf,
+    hgrcpath: Option<OsString>,
+    config_overrides: Vec<(String, String, String)>,
+}
+
+impl NativeBackend {
+    pub fn new(repo_root: &Path) -> NativeBackend {
+        NativeBackend::with_config(repo_root, env::var_os("HGRCPATH"), vec![])
+    }
+
+    pub(crate) fn with_config(
+        repo_root: &Path,
+        hgrcpath: Option<OsString>,
+        config_overrides: Vec<(String, String, String)>,
+    ) -> NativeBackend {
+        NativeBackend {
+            root: repo_root.to_path_buf(),
+            hgrcpath,
+            config_overrides,
+        }
+    }
+}
+
+impl HgBackend for NativeBackend {
+    fn run_command(&self, args: &[OsString]) -> Result<CommandOutput, HgError> {
+        let name = args.first().map(|a| a.to_string_lossy().into_owned());
+        Err(HgError::Unsupported(format!(
+            "hg {} without the hg binary",
+            name.unwrap_or_default()
+        )))
+    }
+
+    fn status(&self) -> Result<Vec<MercurialFile>, HgError> {
+        let config = Config::load_with(
+            Some(&self.root),
+            self.hgrcpath.clone(),
+            &self.config_overrides,
+        )?;
+        native_status_with(
+            &self.root,
+            &IgnoreMatcher::from_config(&self.root, &config)?,
+        )
+    }
+
+    fn log(&self, opts: &LogOptions) -> Result<Vec<Changeset>, HgError> {
+        if opts.revset.is_some() {
+            return Err(HgError::Unsupported(
+                "log revsets without the hg binary".into(),
+            ));
+        }
+        native_log(&self.root, opts)
+    }
+
+    fn cat(&self, path: &Path, rev: Option<&Revset>) -> Result<Box<dyn Read + Send>, HgError> {
+        Ok(Box::new(Cursor::new(native_cat(&self.root, path, rev)?)))
+    }
+}
@@ -0,0 +1,91 @@
+use crate::error::HgError;
+use crate::node::NodeId;
+use crate::revlog::Revision;
+use crate::revset::Revset;
+use crate::store::Store;
+use crate::tags::parse_node_lines;
+use crate::MercurialRepository;
+use log::debug;
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Bookmark {
+    pub name: String,
+    pub node: NodeId,
+    pub rev: Revision,
+    pub active: bool,
+}
+
+impl MercurialRepository {
+    pub fn bookmarks(&self) -> Result<Vec<Bookmark>, HgError> {
+        let store = Store::open(&self.path)?;
+        let file = match store.has_requirement("bookmarksinstore") {
+            true => store.root().join("bookmarks"),
+            false => self.path.join(".hg").join("bookmarks"),
+        };
+        let content = match fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let active = self.working_copy_bookmark()?;
+        let changelog = store.changelog()?;
+        let mut bookmarks = vec![];
+        for (name, node) in parse_node_lines(&content) {
+            let Some(rev) = changelog.rev(&node) else {
+                debug!(
+                    "ignoring bookmark {} pointing to unknown node {}",
+                    name, node
+                );
+                continue;
+            };
+            bookmarks.push(Bookmark {
+                active: active.as_deref() == Some(name.as_str()),
+                name,
+                node,
+                rev,
+            });
+        }
+        bookmarks.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(bookmarks)
+    }
+
+    pub fn create_bookmark(&mut self, name: &str, rev: Option<Revset>) -> Result<(), HgError> {
+        let mut args: Vec<OsString> = vec!["bookmark".into()];
+        if let Some(rev) = rev {
+            args.extend(["-r".into(), rev.into()]);
+        }
+        args.push(name.into());
+        self.hg(&args)?;
+        Ok(())
+    }
+
+    pub fn delete_bookmark(&mut self, name: &str) -> Result<(), HgError> {
+        self.hg(&["bookmark", "--delete", name])?;
+        Ok(())
+    }
+
+    pub fn rename_bookmark(&mut self, old: &str, new: &str) -> Result<(), HgError> {
+        self.hg(&["bookmark", "--rename", old, new])?;
+        Ok(())
+    }
+
+    pub fn move_bookmark(&mut self, name: &str, rev: impl Into<Revset>) -> Result<(), HgError> {
+        let rev = rev.into().to_string();
+        self.hg(&["bookmark", "--force", "-r", &rev, name])?;
+        Ok(())
+    }
+
+    /// Updates the working directory to the bookmark, which makes it the active one.
+    pub fn activate_bookmark(&mut self, name: &str) -> Result<(), HgError> {
+        self.hg(&["update", name])?;
+        self.try_update_statuses()
+    }
+
+    pub fn deactivate_bookmark(&mut self) -> Result<(), HgError> {
+        self.hg(&["bookmark", "--inactive"])?;
+        Ok(())
+    }
+}
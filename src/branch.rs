@@ -0,0 +1,157 @@
+use crate::changeset::{parse_json_changesets, Changeset, LOG_TEMPLATE};
+use crate::error::HgError;
+use crate::node::NodeId;
+use crate::revlog::{Revision, Revlog, NULL_REVISION};
+use crate::revset::Revset;
+use crate::store::Store;
+use crate::MercurialRepository;
+use log::debug;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BranchState {
+    Active,
+    Inactive,
+    Closed,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Branch {
+    pub name: String,
+    pub tip: NodeId,
+    pub state: BranchState,
+}
+
+#[derive(Deserialize)]
+struct RawBranch {
+    branch: String,
+    node: String,
+    active: bool,
+    closed: bool,
+}
+
+pub(crate) fn repo_heads(changelog: &Revlog) -> Result<HashSet<Revision>, HgError> {
+    let mut heads: HashSet<Revision> = (0..=changelog.tip()).collect();
+    for rev in 0..=changelog.tip() {
+        for parent in changelog.parents(rev)? {
+            if parent != NULL_REVISION {
+                heads.remove(&parent);
+            }
+        }
+    }
+    Ok(heads)
+}
+
+impl MercurialRepository {
+    pub fn branches(&self) -> Result<Vec<Branch>, HgError> {
+        match self.cached_branches() {
+            Ok(Some(branches)) => return Ok(branches),
+            Ok(None) => debug!("branch cache is missing or stale"),
+            Err(e) => debug!("unable to read the branch cache: {}", e),
+        }
+        let output = self.hg(&["branches", "--closed", "-T", "json"])?;
+        let raw: Vec<RawBranch> = serde_json::from_slice(&output).map_err(|e| HgError::Parse {
+            line: e.to_string(),
+        })?;
+        raw.into_iter()
+            .map(|b| {
+                Ok(Branch {
+                    name: b.branch,
+                    tip: b.node.parse()?,
+                    state: match (b.closed, b.active) {
+                        (true, _) => BranchState::Closed,
+                        (false, true) => BranchState::Active,
+                        (false, false) => BranchState::Inactive,
+                    },
+                })
+            })
+            .collect()
+    }
+
+    fn cached_branches(&self) -> Result<Option<Vec<Branch>>, HgError> {
+        let cache = self.path.join(".hg").join("cache").join("branch2-served");
+        let content = match fs::read_to_string(&cache) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let changelog = Store::open(&self.path)?.changelog()?;
+        let mut lines = content.lines();
+        let mut header = lines.next().unwrap_or_default().split(' ');
+        let tip_node: NodeId = header.next().unwrap_or_default().parse()?;
+        let tip_rev: Revision =
+            header
+                .next()
+                .and_then(|r| r.parse().ok())
+                .ok_or_else(|| HgError::Parse {
+                    line: content.lines().next().unwrap_or_default().to_string(),
+                })?;
+        if tip_rev != changelog.tip() || changelog.node(tip_rev)? != tip_node {
+            return Ok(None);
+        }
+
+        let mut heads: BTreeMap<String, Vec<(Revision, NodeId, bool)>> = BTreeMap::new();
+        for line in lines.filter(|l| !l.is_empty()) {
+            let mut fields = line.splitn(3, ' ');
+            let (Some(node), Some(state), Some(name)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(HgError::Parse {
+                    line: line.to_string(),
+                });
+            };
+            let node: NodeId = node.parse()?;
+            let Some(rev) = changelog.rev(&node) else {
+                return Ok(None);
+            };
+            heads
+                .entry(name.to_string())
+                .or_default()
+                .push((rev, node, state == "c"));
+        }
+
+        let repo_heads = repo_heads(&changelog)?;
+        let mut branches: Vec<(Revision, Branch)> = heads
+            .into_iter()
+            .map(|(name, mut heads)| {
+                heads.sort();
+                let open: Vec<_> = heads.iter().filter(|(_, _, closed)| !closed).collect();
+                let (tip_rev, tip, _) = **open.last().unwrap_or(&heads.last().unwrap());
+                let state = if open.is_empty() {
+                    BranchState::Closed
+                } else if open.iter().any(|(rev, _, _)| repo_heads.contains(rev)) {
+                    BranchState::Active
+                } else {
+                    BranchState::Inactive
+                };
+                (tip_rev, Branch { name, tip, state })
+            })
+            .collect();
+        branches.sort_by(|(rev_a, a), (rev_b, b)| {
+            (b.state == BranchState::Active)
+                .cmp(&(a.state == BranchState::Active))
+                .then(rev_b.cmp(rev_a))
+        });
+        Ok(Some(branches.into_iter().map(|(_, b)| b).collect()))
+    }
+
+    pub fn heads(&self, branch: Option<&str>) -> Result<Vec<Changeset>, HgError> {
+        let mut args: Vec<OsString> = vec!["heads".into(), "-T".into(), LOG_TEMPLATE.into()];
+        if let Some(branch) = branch {
+            args.extend(["--".into(), Revset::branch(branch).into()]);
+        }
+        let output = self.hg_output(&args)?;
+        match output.code {
+            0 => parse_json_changesets(&output.stdout),
+            1 => Ok(vec![]),
+            code => Err(HgError::CommandFailed {
+                code: Some(code),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            }),
+        }
+    }
+}
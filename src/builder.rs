@@ -0,0 +1,170 @@
+use crate::backend::{checked, CliBackend, CommandServerBackend, HgBackend, NativeBackend};
+use crate::cmdserver::HgCommand;
+use crate::error::HgError;
+use crate::ignore::IgnoreMatcher;
+use crate::init::{clone_args, init_native, CloneOptions};
+use crate::MercurialRepository;
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+enum Backend {
+    CommandServer,
+    Cli,
+    Native,
+    Custom(Arc<dyn HgBackend>),
+}
+
+/// Configures how hg is invoked for a repository. Commands run with
+/// `HGPLAIN=1` unless `plain(false)` is used, so that output does not depend
+/// on the user's aliases, defaults or locale.
+#[derive(Debug, Clone)]
+pub struct RepositoryBuilder {
+    path: PathBuf,
+    binary: PathBuf,
+    config: Vec<(String, String, String)>,
+    plain: bool,
+    encoding: Option<String>,
+    hgrcpath: Option<OsString>,
+    cwd: Option<PathBuf>,
+    backend: Backend,
+}
+
+impl RepositoryBuilder {
+    pub fn new(path: &Path) -> RepositoryBuilder {
+        RepositoryBuilder {
+            path: path.to_path_buf(),
+            binary: PathBuf::from("hg"),
+            config: vec![],
+            plain: true,
+            encoding: None,
+            hgrcpath: None,
+            cwd: None,
+            backend: Backend::CommandServer,
+        }
+    }
+
+    pub fn hg_binary(mut self, binary: impl Into<PathBuf>) -> Self {
+        self.binary = binary.into();
+        self
+    }
+
+    pub fn config(mut self, section: &str, key: &str, value: &str) -> Self {
+        self.config
+            .push((section.to_string(), key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn plain(mut self, plain: bool) -> Self {
+        self.plain = plain;
+        self
+    }
+
+    pub fn encoding(mut self, encoding: &str) -> Self {
+        self.encoding = Some(encoding.to_string());
+        self
+    }
+
+    pub fn hgrcpath(mut self, hgrcpath: impl Into<OsString>) -> Self {
+        self.hgrcpath = Some(hgrcpath.into());
+        self
+    }
+
+    /// Runs hg from `cwd` instead of the repository root. Paths are then
+    /// passed as absolute paths and the repository is selected with `-R`.
+    pub fn cwd(mut self, cwd: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(cwd.into());
+        self
+    }
+
+    /// Spawns a new hg process per command instead of keeping a command
+    /// server running.
+    pub fn cli(mut self) -> Self {
+        self.backend = Backend::Cli;
+        self
+    }
+
+    /// Reads the repository without hg; only status, log and cat are available.
+    pub fn native(mut self) -> Self {
+        self.backend = Backend::Native;
+        self
+    }
+
+    pub fn backend(mut self, backend: impl HgBackend + 'static) -> Self {
+        self.backend = Backend::Custom(Arc::new(backend));
+        self
+    }
+
+    fn command(&self) -> HgCommand {
+        let mut command = HgCommand::new(self.cwd.as_deref().unwrap_or(&self.path));
+        command.binary = self.binary.clone();
+        if command.cwd != self.path {
+            command.repository = Some(self.path.clone());
+            command.config.push("ui.relative-paths=no".to_string());
+        }
+        command.config.extend(
+            self.config
+                .iter()
+                .map(|(section, key, value)| format!("{}.{}={}", section, key, value)),
+        );
+        if self.plain {
+            command.env.push(("HGPLAIN".into(), "1".into()));
+        }
+        if let Some(encoding) = &self.encoding {
+            command.env.push(("HGENCODING".into(), encoding.into()));
+        }
+        if let Some(hgrcpath) = &self.hgrcpath {
+            command.env.push(("HGRCPATH".into(), hgrcpath.clone()));
+        }
+        command
+    }
+
+    /// Creates an empty repository at the builder's path and opens it.
+    pub fn init(self) -> Result<MercurialRepository, HgError> {
+        init_native(&self.path)?;
+        self.build()
+    }
+
+    /// Clones `source` into the builder's path and opens the result. The
+    /// command runs from the current directory, so relative paths work as
+    /// they would on the command line.
+    pub fn clone_from(
+        self,
+        source: &str,
+        opts: &CloneOptions,
+    ) -> Result<MercurialRepository, HgError> {
+        let mut command = self.command();
+        command.cwd = env::current_dir()?;
+        command.repository = None;
+        let args = clone_args(source, &self.path, opts);
+        checked(CliBackend::with_command(command).run_command(&args)?)?;
+        self.build()
+    }
+
+    pub fn build(self) -> Result<MercurialRepository, HgError> {
+        let hgrcpath = self.hgrcpath.clone().or_else(|| env::var_os("HGRCPATH"));
+        let backend: Arc<dyn HgBackend> = match &self.backend {
+            Backend::CommandServer => Arc::new(CommandServerBackend::with_command(self.command())),
+            Backend::Cli => Arc::new(CliBackend::with_command(self.command())),
+            Backend::Native => Arc::new(NativeBackend::with_config(
+                &self.path,
+                hgrcpath.clone(),
+                self.config.clone(),
+            )),
+            Backend::Custom(backend) => backend.clone(),
+        };
+        let mut repo = MercurialRepository {
+            hgrcpath,
+            config_overrides: self.config,
+            path: self.path,
+            files: vec![],
+            ignore: IgnoreMatcher::default(),
+            backend,
+            raw_statuses: vec![],
+        };
+        repo.try_update_statuses()?;
+        Ok(repo)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::cmdserver::{spawn, HgCommand};
+use crate::dirstate::Dirstate;
+use crate::error::HgError;
+use crate::manifest::{parse_manifest_line, resolve_native};
+use crate::mercurial_file::path_from_bytes;
+use crate::revset::Revset;
+use crate::store::{manifest_lines, strip_filelog_metadata, Store};
+use crate::MercurialRepository;
+use std::ffi::OsString;
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+use std::process::{Child, ChildStdout, Stdio};
+
+/// Streams the output of `hg cat` straight from the child process. A failing
+/// command surfaces as an error from `read` once the output is exhausted.
+#[derive(Debug)]
+pub struct FileContent {
+    child: Child,
+    stdout: ChildStdout,
+    finished: bool,
+}
+
+impl FileContent {
+    fn finish(&mut self) -> io::Result<()> {
+        self.finished = true;
+        let status = self.child.wait()?;
+        if status.success() {
+            return Ok(());
+        }
+        let mut stderr = String::new();
+        if let Some(mut pipe) = self.child.stderr.take() {
+            pipe.read_to_string(&mut stderr)?;
+        }
+        Err(io::Error::other(HgError::CommandFailed {
+            code: status.code(),
+            stderr,
+        }))
+    }
+}
+
+impl Read for FileContent {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.finished {
+            return Ok(0);
+        }
+        let read = self.stdout.read(buf)?;
+        if read == 0 && !buf.is_empty() {
+            self.finish()?;
+        }
+        Ok(read)
+    }
+}
+
+impl Drop for FileContent {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+pub(crate) fn spawn_cat(
+    command: &HgCommand,
+    path: &Path,
+    rev: Option<&Revset>,
+) -> Result<FileContent, HgError> {
+    let mut args: Vec<OsString> = vec!["cat".into()];
+    if let Some(rev) = rev {
+        args.extend(["-r".into(), rev.clone().into()]);
+    }
+    args.extend(["--".into(), command.path_arg(path)]);
+    let mut child = spawn(
+        command
+            .command(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )?;
+    let Some(stdout) = child.stdout.take() else {
+        return Err(HgError::Io(ErrorKind::BrokenPipe.into()));
+    };
+    Ok(FileContent {
+        child,
+        stdout,
+        finished: false,
+    })
+}
+
+pub(crate) fn native_cat(
+    root: &Path,
+    path: &Path,
+    rev: Option<&Revset>,
+) -> Result<Vec<u8>, HgError> {
+    let node = match rev {
+        Some(rev) => resolve_native(root, rev)?.ok_or_else(|| {
+            HgError::Unsupported(format!("resolving {} without the hg binary", rev))
+        })?,
+        None => Dirstate::read(root)?.parents[0],
+    };
+    let store = Store::open(root)?;
+    let text = store.manifest_text(&node)?;
+    let entry = manifest_lines(&text)
+        .find(|(name, _)| path_from_bytes(name) == path)
+        .map(|(name, value)| parse_manifest_line(name, value))
+        .ok_or_else(|| HgError::UnknownFile(path.to_path_buf()))??;
+    let data = store.filelog(path)?.revision_by_node(&entry.node)?;
+    Ok(strip_filelog_metadata(&data).to_vec())
+}
+
+impl MercurialRepository {
+    pub fn cat(&self, path: &Path, rev: Option<Revset>) -> Result<Box<dyn Read + Send>, HgError> {
+        self.backend.cat(self.relative_path(path), rev.as_ref())
+    }
+}
@@ -0,0 +1,252 @@
+use crate::dirstate::Dirstate;
+use crate::error::HgError;
+use crate::mercurial_file::path_from_bytes;
+use crate::node::NodeId;
+use crate::revlog::{Revision, NULL_REVISION};
+use crate::revset::Revset;
+use crate::store::Store;
+use crate::MercurialRepository;
+use log::debug;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+pub(crate) const LOG_TEMPLATE: &str =
+    "json(rev, node, parents, user, date, branch, desc, files, extras)";
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Changeset {
+    pub rev: Revision,
+    pub node: NodeId,
+    pub parents: Vec<NodeId>,
+    pub author: String,
+    pub date: i64,
+    pub timezone: i32,
+    pub branch: String,
+    pub description: String,
+    pub files: Vec<PathBuf>,
+    pub extra: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LogOptions {
+    pub revset: Option<Revset>,
+    pub limit: Option<usize>,
+    pub files: Vec<PathBuf>,
+    pub follow: bool,
+}
+
+#[derive(Deserialize)]
+struct RawChangeset {
+    rev: Revision,
+    node: String,
+    #[serde(default)]
+    parents: Vec<String>,
+    user: String,
+    date: (f64, i32),
+    branch: String,
+    desc: String,
+    #[serde(default)]
+    files: Vec<String>,
+    #[serde(default)]
+    extras: BTreeMap<String, String>,
+}
+
+impl TryFrom<RawChangeset> for Changeset {
+    type Error = HgError;
+
+    fn try_from(raw: RawChangeset) -> Result<Self, Self::Error> {
+        let parents = raw
+            .parents
+            .iter()
+            .map(|p| p.parse::<NodeId>())
+            .filter(|p| !matches!(p, Ok(p) if p.is_null()))
+            .collect::<Result<_, _>>()?;
+        Ok(Changeset {
+            rev: raw.rev,
+            node: raw.node.parse()?,
+            parents,
+            author: raw.user,
+            date: raw.date.0 as i64,
+            timezone: raw.date.1,
+            branch: raw.branch,
+            description: raw.desc,
+            files: raw.files.into_iter().map(PathBuf::from).collect(),
+            extra: raw.extras,
+        })
+    }
+}
+
+pub(crate) fn parse_json_changesets(output: &[u8]) -> Result<Vec<Changeset>, HgError> {
+    let raw: Vec<RawChangeset> = serde_json::from_slice(output).map_err(|e| HgError::Parse {
+        line: e.to_string(),
+    })?;
+    raw.into_iter().map(Changeset::try_from).collect()
+}
+
+pub(crate) fn parse_changelog_entry(
+    rev: Revision,
+    node: NodeId,
+    parents: Vec<NodeId>,
+    text: &[u8],
+) -> Result<Changeset, HgError> {
+    let parse_error = || HgError::Parse {
+        line: String::from_utf8_lossy(text.split(|b| *b == b'\n').nth(2).unwrap_or(text))
+            .into_owned(),
+    };
+    let (header, description) = match text.windows(2).position(|w| w == b"\n\n") {
+        Some(i) => (&text[..i], &text[i + 2..]),
+        None => (text, &b""[..]),
+    };
+    let mut lines = header.split(|b| *b == b'\n');
+    let _manifest = lines.next();
+    let author = String::from_utf8_lossy(lines.next().unwrap_or_default()).into_owned();
+    let date_line = String::from_utf8_lossy(lines.next().ok_or_else(parse_error)?).into_owned();
+    let mut date_fields = date_line.splitn(3, ' ');
+    let date = date_fields
+        .next()
+        .and_then(|d| d.parse::<f64>().ok())
+        .ok_or_else(parse_error)?;
+    let timezone = date_fields
+        .next()
+        .and_then(|tz| tz.parse::<i32>().ok())
+        .ok_or_else(parse_error)?;
+    let extra: BTreeMap<String, String> = date_fields
+        .next()
+        .map(|extra| {
+            extra
+                .split('\0')
+                .filter_map(|item| {
+                    let item = unescape_extra(item);
+                    let (key, value) = item.split_once(':')?;
+                    Some((key.to_string(), value.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(Changeset {
+        rev,
+        node,
+        parents,
+        author,
+        date: date as i64,
+        timezone,
+        branch: extra
+            .get("branch")
+            .cloned()
+            .unwrap_or_else(|| "default".to_string()),
+        description: String::from_utf8_lossy(description).into_owned(),
+        files: lines.map(path_from_bytes).collect(),
+        extra,
+    })
+}
+
+fn unescape_extra(item: &str) -> String {
+    let mut out = String::with_capacity(item.len());
+    let mut chars = item.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some('0') => out.push('\0'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn touches(changeset: &Changeset, files: &[PathBuf]) -> bool {
+    files.is_empty()
+        || changeset
+            .files
+            .iter()
+            .any(|f| files.iter().any(|wanted| f.starts_with(wanted)))
+}
+
+pub(crate) fn log_args(opts: &LogOptions, path_arg: impl Fn(&Path) -> OsString) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec!["log".into(), "-T".into(), LOG_TEMPLATE.into()];
+    if let Some(revset) = &opts.revset {
+        args.extend(["-r".into(), revset.clone().into()]);
+    }
+    if let Some(limit) = opts.limit {
+        args.extend(["-l".into(), limit.to_string().into()]);
+    }
+    if opts.follow {
+        args.push("-f".into());
+    }
+    args.push("--".into());
+    args.extend(opts.files.iter().map(|f| path_arg(f)));
+    args
+}
+
+pub(crate) fn native_log(root: &Path, opts: &LogOptions) -> Result<Vec<Changeset>, HgError> {
+    let changelog = Store::open(root)?.changelog()?;
+    let files: Vec<PathBuf> = opts
+        .files
+        .iter()
+        .map(|f| f.strip_prefix(root).unwrap_or(f).to_path_buf())
+        .collect();
+    let mut wanted: Option<HashSet<Revision>> = None;
+    if opts.follow {
+        let parents = Dirstate::read(root)?.parents;
+        let mut pending: Vec<Revision> = parents.iter().filter_map(|p| changelog.rev(p)).collect();
+        let mut ancestors = HashSet::new();
+        while let Some(rev) = pending.pop() {
+            if rev != NULL_REVISION && ancestors.insert(rev) {
+                pending.extend(changelog.parents(rev)?);
+            }
+        }
+        wanted = Some(ancestors);
+    }
+
+    let mut changesets = vec![];
+    for rev in (0..=changelog.tip()).rev() {
+        if opts.limit.is_some_and(|limit| changesets.len() >= limit) {
+            break;
+        }
+        if wanted.as_ref().is_some_and(|w| !w.contains(&rev)) {
+            continue;
+        }
+        let entry = changelog.entry(rev)?;
+        let parents = [entry.p1, entry.p2]
+            .into_iter()
+            .filter(|p| *p != NULL_REVISION)
+            .map(|p| changelog.node(p))
+            .collect::<Result<_, _>>()?;
+        let text = changelog.revision(rev)?;
+        let changeset = parse_changelog_entry(rev, entry.node, parents, &text)?;
+        if touches(&changeset, &files) {
+            changesets.push(changeset);
+        }
+    }
+    Ok(changesets)
+}
+
+impl MercurialRepository {
+    pub fn log(&self, opts: &LogOptions) -> Result<impl Iterator<Item = Changeset>, HgError> {
+        let opts = LogOptions {
+            files: opts
+                .files
+                .iter()
+                .map(|f| self.relative_path(f).to_path_buf())
+                .collect(),
+            ..opts.clone()
+        };
+        let changesets = match self.backend.log(&opts) {
+            Ok(changesets) => changesets,
+            Err(HgError::BinaryNotFound) if opts.revset.is_none() => {
+                debug!("hg not found, reading the changelog directly");
+                native_log(&self.path, &opts)?
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(changesets.into_iter())
+    }
+}
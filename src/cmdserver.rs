@@ -0,0 +1,290 @@
+use crate::error::HgError;
+use log::debug;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// How to launch hg: the binary, the directory it runs in and the global
+/// options and environment shared by every process the crate spawns.
+#[derive(Debug, Clone)]
+pub(crate) struct HgCommand {
+    pub(crate) binary: PathBuf,
+    pub(crate) cwd: PathBuf,
+    pub(crate) repository: Option<PathBuf>,
+    pub(crate) config: Vec<String>,
+    pub(crate) env: Vec<(OsString, OsString)>,
+}
+
+impl HgCommand {
+    pub(crate) fn new(cwd: &Path) -> HgCommand {
+        HgCommand {
+            binary: PathBuf::from("hg"),
+            cwd: cwd.to_path_buf(),
+            repository: None,
+            config: vec![],
+            env: vec![],
+        }
+    }
+
+    pub(crate) fn path_arg(&self, path: &Path) -> OsString {
+        match &self.repository {
+            Some(root) => root.join(path).into_os_string(),
+            None => path.as_os_str().to_owned(),
+        }
+    }
+
+    pub(crate) fn command<S: AsRef<OsStr>>(&self, args: &[S]) -> Command {
+        let mut command = Command::new(&self.binary);
+        command.current_dir(&self.cwd);
+        command.envs(self.env.iter().map(|(key, value)| (key, value)));
+        if let Some(repository) = &self.repository {
+            command.arg("-R").arg(repository);
+        }
+        for config in &self.config {
+            command.arg("--config").arg(config);
+        }
+        command.args(args);
+        command
+    }
+}
+
+pub(crate) fn spawn(command: &mut Command) -> Result<Child, HgError> {
+    command.spawn().map_err(|e| match e.kind() {
+        ErrorKind::NotFound => HgError::BinaryNotFound,
+        _ => HgError::Io(e),
+    })
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct CommandOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub code: i32,
+}
+
+struct ServerProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+pub struct CommandServer {
+    hg: HgCommand,
+    process: Option<ServerProcess>,
+    capabilities: Vec<String>,
+    encoding: String,
+    pid: Option<u32>,
+}
+
+impl fmt::Debug for CommandServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandServer")
+            .field("binary", &self.hg.binary)
+            .field("cwd", &self.hg.cwd)
+            .field("running", &self.process.is_some())
+            .field("capabilities", &self.capabilities)
+            .field("encoding", &self.encoding)
+            .field("pid", &self.pid)
+            .finish()
+    }
+}
+
+impl Drop for CommandServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl CommandServer {
+    pub fn new(cwd: &Path) -> CommandServer {
+        CommandServer::with_command(HgCommand::new(cwd))
+    }
+
+    pub(crate) fn with_command(hg: HgCommand) -> CommandServer {
+        CommandServer {
+            hg,
+            process: None,
+            capabilities: vec![],
+            encoding: String::new(),
+            pid: None,
+        }
+    }
+
+    pub fn capabilities(&self) -> &[String] {
+        &self.capabilities
+    }
+
+    pub fn encoding(&self) -> &str {
+        &self.encoding
+    }
+
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    pub fn is_running(&mut self) -> bool {
+        match &mut self.process {
+            Some(process) => matches!(process.child.try_wait(), Ok(None)),
+            None => false,
+        }
+    }
+
+    pub fn start(&mut self) -> Result<(), HgError> {
+        self.stop();
+        let mut child = spawn(
+            self.hg
+                .command(&["serve", "--cmdserver", "pipe"])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null()),
+        )?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(HgError::Io(ErrorKind::BrokenPipe.into()));
+        };
+        let mut process = ServerProcess {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        };
+        if let Err(e) = self.handshake(&mut process) {
+            let _ = process.child.kill();
+            let _ = process.child.wait();
+            return Err(e);
+        }
+        debug!("started command server with pid {:?}", self.pid);
+        self.process = Some(process);
+        Ok(())
+    }
+
+    fn handshake(&mut self, process: &mut ServerProcess) -> Result<(), HgError> {
+        let (channel, hello) = process.read_message()?;
+        let hello = String::from_utf8_lossy(&hello).into_owned();
+        if channel != b'o' {
+            return Err(HgError::Parse { line: hello });
+        }
+        for line in hello.lines() {
+            match line.split_once(": ") {
+                Some(("capabilities", caps)) => {
+                    self.capabilities = caps.split(' ').map(String::from).collect();
+                }
+                Some(("encoding", encoding)) => self.encoding = encoding.to_string(),
+                Some(("pid", pid)) => self.pid = pid.parse().ok(),
+                _ => {}
+            }
+        }
+        if !self.capabilities.iter().any(|c| c == "runcommand") {
+            return Err(HgError::Parse { line: hello });
+        }
+        Ok(())
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(process) = self.process.take() {
+            let ServerProcess {
+                mut child, stdin, ..
+            } = process;
+            drop(stdin);
+            let _ = child.wait();
+        }
+    }
+
+    pub fn run_command<S: AsRef<OsStr>>(&mut self, args: &[S]) -> Result<CommandOutput, HgError> {
+        if !self.is_running() {
+            self.start()?;
+        }
+        let request = encode_command(args);
+        if let Err(e) = self.process_mut()?.write_command(&request) {
+            debug!("command server went away ({}), restarting", e);
+            self.start()?;
+            self.process_mut()?.write_command(&request)?;
+        }
+        let result = self.process_mut()?.read_response();
+        if result.is_err() {
+            self.stop();
+        }
+        result
+    }
+
+    fn process_mut(&mut self) -> Result<&mut ServerProcess, HgError> {
+        self.process
+            .as_mut()
+            .ok_or_else(|| HgError::Io(ErrorKind::NotConnected.into()))
+    }
+}
+
+impl ServerProcess {
+    fn read_header(&mut self) -> Result<(u8, u32), HgError> {
+        let mut header = [0; 5];
+        self.stdout.read_exact(&mut header)?;
+        let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+        Ok((header[0], length))
+    }
+
+    fn read_message(&mut self) -> Result<(u8, Vec<u8>), HgError> {
+        let (channel, length) = self.read_header()?;
+        let mut data = vec![0; length as usize];
+        self.stdout.read_exact(&mut data)?;
+        Ok((channel, data))
+    }
+
+    fn write_command(&mut self, request: &[u8]) -> std::io::Result<()> {
+        self.stdin.write_all(b"runcommand\n")?;
+        self.stdin
+            .write_all(&(request.len() as u32).to_be_bytes())?;
+        self.stdin.write_all(request)?;
+        self.stdin.flush()
+    }
+
+    fn read_response(&mut self) -> Result<CommandOutput, HgError> {
+        let mut output = CommandOutput::default();
+        loop {
+            let (channel, length) = self.read_header()?;
+            match channel {
+                b'I' | b'L' => {
+                    self.stdin.write_all(&0u32.to_be_bytes())?;
+                    self.stdin.flush()?;
+                    continue;
+                }
+                c if c.is_ascii_uppercase() => {
+                    return Err(HgError::Parse {
+                        line: format!("unexpected required channel {:?}", c as char),
+                    });
+                }
+                _ => {}
+            }
+            let mut data = vec![0; length as usize];
+            self.stdout.read_exact(&mut data)?;
+            match channel {
+                b'o' => output.stdout.extend_from_slice(&data),
+                b'e' => output.stderr.extend_from_slice(&data),
+                b'r' => {
+                    let code: [u8; 4] = data.as_slice().try_into().map_err(|_| HgError::Parse {
+                        line: String::from_utf8_lossy(&data).into_owned(),
+                    })?;
+                    output.code = i32::from_be_bytes(code);
+                    return Ok(output);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn encode_command<S: AsRef<OsStr>>(args: &[S]) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    args.iter()
+        .map(|a| a.as_ref().as_bytes())
+        .collect::<Vec<_>>()
+        .join(&0)
+}
+
+#[cfg(not(unix))]
+fn encode_command<S: AsRef<OsStr>>(args: &[S]) -> Vec<u8> {
+    args.iter()
+        .map(|a| a.as_ref().to_string_lossy().into_owned().into_bytes())
+        .collect::<Vec<_>>()
+        .join(&0)
+}
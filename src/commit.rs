@@ -0,0 +1,71 @@
+use crate::changeset::LogOptions;
+use crate::error::HgError;
+use crate::node::NodeId;
+use crate::revset::Revset;
+use crate::MercurialRepository;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Default)]
+pub struct CommitOptions {
+    pub message: String,
+    pub user: Option<String>,
+    pub date: Option<(i64, i32)>,
+    pub files: Vec<PathBuf>,
+    pub include: Vec<String>,
+    pub addremove: bool,
+    pub close_branch: bool,
+    pub amend: bool,
+}
+
+impl MercurialRepository {
+    pub fn commit(&mut self, opts: &CommitOptions) -> Result<NodeId, HgError> {
+        let mut args: Vec<OsString> = vec!["commit".into(), "-m".into(), (&opts.message).into()];
+        if let Some(user) = &opts.user {
+            args.extend(["-u".into(), user.into()]);
+        }
+        if let Some((date, timezone)) = opts.date {
+            args.extend(["-d".into(), format!("{} {}", date, timezone).into()]);
+        }
+        for pattern in &opts.include {
+            args.extend(["-I".into(), pattern.into()]);
+        }
+        if opts.addremove {
+            args.push("--addremove".into());
+        }
+        if opts.close_branch {
+            args.push("--close-branch".into());
+        }
+        if opts.amend {
+            args.push("--amend".into());
+        }
+        args.push("--".into());
+        args.extend(opts.files.iter().map(|f| self.path_arg(f)));
+
+        let output = self.hg_output(&args)?;
+        let nothing_changed = [&output.stdout, &output.stderr]
+            .iter()
+            .any(|o| String::from_utf8_lossy(o).contains("nothing changed"));
+        if output.code == 1 && nothing_changed {
+            return Err(HgError::NothingChanged);
+        }
+        if output.code != 0 {
+            return Err(HgError::CommandFailed {
+                code: Some(output.code),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
+        }
+        self.try_update_statuses()?;
+        let tip = LogOptions {
+            revset: Some(Revset::from(".")),
+            limit: Some(1),
+            ..LogOptions::default()
+        };
+        self.log(&tip)?
+            .next()
+            .map(|changeset| changeset.node)
+            .ok_or_else(|| HgError::Parse {
+                line: String::from_utf8_lossy(&output.stdout).into_owned(),
+            })
+    }
+}
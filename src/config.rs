@@ -0,0 +1,253 @@
+use crate::error::HgError;
+use crate::MercurialRepository;
+use log::debug;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ConfigValue {
+    pub value: String,
+    pub source: PathBuf,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    sections: BTreeMap<String, BTreeMap<String, ConfigValue>>,
+}
+
+struct Patterns {
+    section: Regex,
+    item: Regex,
+    continuation: Regex,
+    comment: Regex,
+    empty: Regex,
+    unset: Regex,
+    include: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        section: Regex::new(r"^\[([^\[]+)\]").unwrap(),
+        item: Regex::new(r"^([^=\s][^=]*?)\s*=\s*((.*\S)?)").unwrap(),
+        continuation: Regex::new(r"^\s+(\S|\S.*\S)\s*$").unwrap(),
+        comment: Regex::new(r"^(;|#)").unwrap(),
+        empty: Regex::new(r"^(;|#|\s*$)").unwrap(),
+        unset: Regex::new(r"^%unset\s+(\S+)").unwrap(),
+        include: Regex::new(r"^%include\s+(\S|\S.*\S)\s*$").unwrap(),
+    })
+}
+
+pub(crate) fn expand_path(path: &str) -> PathBuf {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"));
+    match (path.strip_prefix("~/"), home) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn rc_files_in(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+    let mut files: Vec<PathBuf> = fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "rc"))
+        .collect();
+    files.sort();
+    files
+}
+
+fn system_and_user_files(hgrcpath: Option<OsString>) -> Vec<PathBuf> {
+    if let Some(hgrcpath) = hgrcpath {
+        return env::split_paths(&hgrcpath)
+            .filter(|p| !p.as_os_str().is_empty())
+            .flat_map(|p| rc_files_in(&expand_path(&p.to_string_lossy())))
+            .collect();
+    }
+    let mut files = vec![];
+    if cfg!(unix) {
+        files.push(PathBuf::from("/etc/mercurial/hgrc"));
+        files.extend(rc_files_in(Path::new("/etc/mercurial/hgrc.d")));
+    }
+    files.push(expand_path("~/.hgrc"));
+    if cfg!(windows) {
+        files.push(expand_path("~/mercurial.ini"));
+    }
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .unwrap_or_else(|| expand_path("~/.config"));
+    files.push(config_home.join("hg").join("hgrc"));
+    files
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "1" | "yes" | "true" | "on" | "always" => Some(true),
+        "0" | "no" | "false" | "off" | "never" => Some(false),
+        _ => None,
+    }
+}
+
+impl Config {
+    /// Loads the system and user files, or the entries of `HGRCPATH` when it
+    /// is set, followed by the repository's `.hg/hgrc`.
+    pub fn load(repo_root: Option<&Path>) -> Result<Config, HgError> {
+        Config::load_with(repo_root, env::var_os("HGRCPATH"), &[])
+    }
+
+    /// `overrides` are `(section, key, value)` items applied last, like
+    /// `--config` arguments.
+    pub(crate) fn load_with(
+        repo_root: Option<&Path>,
+        hgrcpath: Option<OsString>,
+        overrides: &[(String, String, String)],
+    ) -> Result<Config, HgError> {
+        let mut files = system_and_user_files(hgrcpath);
+        if let Some(root) = repo_root.filter(|_| env::var_os("HGRCSKIPREPO").is_none()) {
+            files.push(root.join(".hg").join("hgrc"));
+        }
+        let mut config = Config::from_files(&files)?;
+        for (section, key, value) in overrides {
+            config.set(section, key, value, Path::new("--config"), 0);
+        }
+        Ok(config)
+    }
+
+    pub fn from_files(files: &[PathBuf]) -> Result<Config, HgError> {
+        let mut config = Config::default();
+        for file in files {
+            config.read(file)?;
+        }
+        Ok(config)
+    }
+
+    pub fn read(&mut self, file: &Path) -> Result<(), HgError> {
+        match fs::read(file) {
+            Ok(content) => self.parse(&String::from_utf8_lossy(&content), file),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn parse(&mut self, content: &str, source: &Path) -> Result<(), HgError> {
+        let patterns = patterns();
+        let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+        let mut section = String::new();
+        let mut item: Option<String> = None;
+        for (index, line) in content.lines().enumerate() {
+            let number = index + 1;
+            if let Some(key) = &item {
+                if patterns.comment.is_match(line) {
+                    continue;
+                }
+                if let Some(m) = patterns.continuation.captures(line) {
+                    if let Some(value) = self
+                        .sections
+                        .get_mut(&section)
+                        .and_then(|items| items.get_mut(key))
+                    {
+                        value.value.push('\n');
+                        value.value.push_str(&m[1]);
+                        value.source = source.to_path_buf();
+                        value.line = number;
+                    }
+                    continue;
+                }
+                item = None;
+            }
+            if let Some(m) = patterns.include.captures(line) {
+                let target = source
+                    .parent()
+                    .unwrap_or(Path::new(""))
+                    .join(expand_path(&m[1]));
+                debug!("including {} from {}", target.display(), source.display());
+                self.read(&target)?;
+                continue;
+            }
+            if patterns.empty.is_match(line) {
+                continue;
+            }
+            if let Some(m) = patterns.section.captures(line) {
+                section = m[1].to_string();
+                continue;
+            }
+            if let Some(m) = patterns.item.captures(line) {
+                let key = m[1].to_string();
+                self.set(&section, &key, &m[2], source, number);
+                item = Some(key);
+                continue;
+            }
+            if let Some(m) = patterns.unset.captures(line) {
+                if let Some(items) = self.sections.get_mut(&section) {
+                    items.remove(&m[1]);
+                }
+                continue;
+            }
+            return Err(HgError::Config {
+                origin: format!("{}:{}", source.display(), number),
+                reason: line.trim_end().to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn set(&mut self, section: &str, key: &str, value: &str, source: &Path, line: usize) {
+        self.sections
+            .entry(section.to_string())
+            .or_default()
+            .insert(
+                key.to_string(),
+                ConfigValue {
+                    value: value.to_string(),
+                    source: source.to_path_buf(),
+                    line,
+                },
+            );
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&ConfigValue> {
+        self.sections.get(section)?.get(key)
+    }
+
+    pub fn get_str(&self, section: &str, key: &str) -> Option<&str> {
+        self.get(section, key).map(|v| v.value.as_str())
+    }
+
+    pub fn get_bool(&self, section: &str, key: &str) -> Option<bool> {
+        parse_bool(self.get_str(section, key)?)
+    }
+
+    pub fn sections(&self) -> impl Iterator<Item = &str> {
+        self.sections.keys().map(String::as_str)
+    }
+
+    pub fn items(&self, section: &str) -> impl Iterator<Item = (&str, &ConfigValue)> {
+        self.sections
+            .get(section)
+            .into_iter()
+            .flatten()
+            .map(|(key, value)| (key.as_str(), value))
+    }
+}
+
+impl MercurialRepository {
+    pub fn config(&self) -> Result<Config, HgError> {
+        Config::load_with(
+            Some(&self.path),
+            self.hgrcpath.clone(),
+            &self.config_overrides,
+        )
+    }
+}
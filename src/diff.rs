@@ -0,0 +1,206 @@
+use crate::error::HgError;
+use crate::revset::Revset;
+use crate::MercurialRepository;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LineTag {
+    Context,
+    Added,
+    Removed,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DiffLine {
+    pub tag: LineTag,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Hunk {
+    pub old_start: u32,
+    pub old_len: u32,
+    pub new_start: u32,
+    pub new_len: u32,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct FileDiff {
+    pub old_path: Option<PathBuf>,
+    pub new_path: Option<PathBuf>,
+    pub old_mode: Option<u32>,
+    pub new_mode: Option<u32>,
+    pub binary: bool,
+    pub copied_from: Option<PathBuf>,
+    pub renamed_from: Option<PathBuf>,
+    pub hunks: Vec<Hunk>,
+}
+
+impl Hunk {
+    fn is_complete(&self) -> bool {
+        let old = self
+            .lines
+            .iter()
+            .filter(|l| l.tag != LineTag::Added)
+            .count();
+        let new = self
+            .lines
+            .iter()
+            .filter(|l| l.tag != LineTag::Removed)
+            .count();
+        old >= self.old_len as usize && new >= self.new_len as usize
+    }
+}
+
+impl FileDiff {
+    pub fn mode_changed(&self) -> bool {
+        self.old_mode.is_some() && self.new_mode.is_some() && self.old_mode != self.new_mode
+    }
+}
+
+fn parse_range(range: &str, line: &str) -> Result<(u32, u32), HgError> {
+    let parse_error = || HgError::Parse {
+        line: line.to_string(),
+    };
+    let (start, len) = match range.split_once(',') {
+        Some((start, len)) => (start, len.parse().map_err(|_| parse_error())?),
+        None => (range, 1),
+    };
+    Ok((start.parse().map_err(|_| parse_error())?, len))
+}
+
+fn parse_hunk_header(line: &str) -> Result<Hunk, HgError> {
+    let parse_error = || HgError::Parse {
+        line: line.to_string(),
+    };
+    let mut fields = line.split(' ').skip(1);
+    let old = fields
+        .next()
+        .and_then(|f| f.strip_prefix('-'))
+        .ok_or_else(parse_error)?;
+    let new = fields
+        .next()
+        .and_then(|f| f.strip_prefix('+'))
+        .ok_or_else(parse_error)?;
+    let (old_start, old_len) = parse_range(old, line)?;
+    let (new_start, new_len) = parse_range(new, line)?;
+    Ok(Hunk {
+        old_start,
+        old_len,
+        new_start,
+        new_len,
+        lines: vec![],
+    })
+}
+
+fn parse_mode(mode: &str, line: &str) -> Result<u32, HgError> {
+    u32::from_str_radix(mode.trim(), 8).map_err(|_| HgError::Parse {
+        line: line.to_string(),
+    })
+}
+
+fn strip_side(path: &str, side: &str) -> Option<PathBuf> {
+    match path {
+        "/dev/null" => None,
+        _ => Some(PathBuf::from(path.strip_prefix(side).unwrap_or(path))),
+    }
+}
+
+pub(crate) fn parse_git_diff(output: &[u8]) -> Result<Vec<FileDiff>, HgError> {
+    let text = String::from_utf8_lossy(output);
+    let mut diffs: Vec<FileDiff> = vec![];
+    for line in text.lines() {
+        if let Some(paths) = line.strip_prefix("diff --git ") {
+            let (old, new) = paths.split_once(" b/").ok_or_else(|| HgError::Parse {
+                line: line.to_string(),
+            })?;
+            diffs.push(FileDiff {
+                old_path: strip_side(old, "a/"),
+                new_path: Some(PathBuf::from(new)),
+                ..FileDiff::default()
+            });
+            continue;
+        }
+        let Some(diff) = diffs.last_mut() else {
+            continue;
+        };
+        if let Some(hunk) = diff.hunks.last_mut().filter(|h| !h.is_complete()) {
+            let tag = match line.chars().next() {
+                Some(' ') => Some(LineTag::Context),
+                Some('+') => Some(LineTag::Added),
+                Some('-') => Some(LineTag::Removed),
+                _ => None,
+            };
+            if let Some(tag) = tag {
+                hunk.lines.push(DiffLine {
+                    tag,
+                    text: line[1..].to_string(),
+                });
+                continue;
+            }
+        }
+        if line.starts_with("\\ ") {
+            continue;
+        } else if line.starts_with("@@ ") {
+            diff.hunks.push(parse_hunk_header(line)?);
+        } else if let Some(mode) = line.strip_prefix("old mode ") {
+            diff.old_mode = Some(parse_mode(mode, line)?);
+        } else if let Some(mode) = line.strip_prefix("new mode ") {
+            diff.new_mode = Some(parse_mode(mode, line)?);
+        } else if let Some(mode) = line.strip_prefix("new file mode ") {
+            diff.old_path = None;
+            diff.new_mode = Some(parse_mode(mode, line)?);
+        } else if let Some(mode) = line.strip_prefix("deleted file mode ") {
+            diff.new_path = None;
+            diff.old_mode = Some(parse_mode(mode, line)?);
+        } else if let Some(source) = line.strip_prefix("copy from ") {
+            diff.copied_from = Some(PathBuf::from(source));
+        } else if let Some(source) = line.strip_prefix("rename from ") {
+            diff.renamed_from = Some(PathBuf::from(source));
+        } else if let Some(old) = line.strip_prefix("--- ") {
+            diff.old_path = strip_side(old.trim_end_matches('\t'), "a/");
+        } else if let Some(new) = line.strip_prefix("+++ ") {
+            diff.new_path = strip_side(new.trim_end_matches('\t'), "b/");
+        } else if line.starts_with("Binary file") || line.starts_with("GIT binary patch") {
+            diff.binary = true;
+        }
+    }
+    Ok(diffs)
+}
+
+pub(crate) fn diff_args(
+    path: OsString,
+    from_rev: Option<&Revset>,
+    to_rev: Option<&Revset>,
+) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec!["diff".into(), "--git".into()];
+    let from_rev = from_rev
+        .cloned()
+        .or_else(|| to_rev.map(|_| Revset::from(".")));
+    for rev in [from_rev, to_rev.cloned()].into_iter().flatten() {
+        args.extend(["-r".into(), rev.into()]);
+    }
+    args.extend(["--".into(), path]);
+    args
+}
+
+impl MercurialRepository {
+    pub fn diff(
+        &self,
+        path: &Path,
+        from_rev: Option<Revset>,
+        to_rev: Option<Revset>,
+    ) -> Result<FileDiff, HgError> {
+        let relative = self.relative_path(path).to_path_buf();
+        let diffs = self
+            .backend
+            .diff(&relative, from_rev.as_ref(), to_rev.as_ref())?;
+        Ok(diffs.into_iter().next().unwrap_or_else(|| FileDiff {
+            old_path: Some(relative.clone()),
+            new_path: Some(relative),
+            ..FileDiff::default()
+        }))
+    }
+}
@@ -0,0 +1,334 @@
+use crate::error::HgError;
+use crate::mercurial_file::path_from_bytes;
+use crate::node::NodeId;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+pub const SIZE_NON_NORMAL: i32 = -1;
+pub const SIZE_FROM_OTHER_PARENT: i32 = -2;
+pub const MTIME_UNSET: i32 = -1;
+
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+const V1_HEADER_LEN: usize = 40;
+const V1_ENTRY_HEADER_LEN: usize = 17;
+
+const V2_MARKER: &[u8] = b"dirstate-v2\n";
+const V2_STORED_NODE_LEN: usize = 32;
+const V2_TREE_METADATA_LEN: usize = 44;
+const V2_DOCKET_HEADER_LEN: usize =
+    V2_MARKER.len() + 2 * V2_STORED_NODE_LEN + V2_TREE_METADATA_LEN + 4 + 1;
+const V2_NODE_LEN: usize = 44;
+
+const V2_WDIR_TRACKED: u16 = 1 << 0;
+const V2_P1_TRACKED: u16 = 1 << 1;
+const V2_P2_INFO: u16 = 1 << 2;
+const V2_MODE_EXEC_PERM: u16 = 1 << 3;
+const V2_MODE_IS_SYMLINK: u16 = 1 << 4;
+const V2_HAS_MODE_AND_SIZE: u16 = 1 << 10;
+const V2_HAS_MTIME: u16 = 1 << 11;
+const V2_MTIME_SECOND_AMBIGUOUS: u16 = 1 << 12;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EntryState {
+    Normal,
+    Added,
+    Removed,
+    Merged,
+}
+
+impl EntryState {
+    fn from_v1(state: u8) -> Option<EntryState> {
+        match state {
+            b'n' => Some(EntryState::Normal),
+            b'a' => Some(EntryState::Added),
+            b'r' => Some(EntryState::Removed),
+            b'm' => Some(EntryState::Merged),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DirstateEntry {
+    pub state: EntryState,
+    pub mode: u32,
+    pub size: i32,
+    pub mtime: i32,
+    pub mtime_nanos: u32,
+    pub copy_source: Option<PathBuf>,
+}
+
+impl DirstateEntry {
+    pub fn is_exec(&self) -> bool {
+        self.mode & 0o100 != 0
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode & 0o170000 == S_IFLNK
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Dirstate {
+    pub parents: [NodeId; 2],
+    pub entries: BTreeMap<PathBuf, DirstateEntry>,
+}
+
+struct Reader<'a> {
+    path: &'a Path,
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn corrupt(&self, reason: &str) -> HgError {
+        HgError::Corrupt {
+            path: self.path.to_path_buf(),
+            reason: reason.to_string(),
+        }
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], HgError> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| self.corrupt("unexpected end of data"))
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, HgError> {
+        let b = self.bytes(offset, 2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, HgError> {
+        let b = self.bytes(offset, 4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i32(&self, offset: usize) -> Result<i32, HgError> {
+        Ok(self.u32(offset)? as i32)
+    }
+
+    fn node(&self, offset: usize) -> Result<NodeId, HgError> {
+        Ok(NodeId::from_bytes(self.bytes(offset, crate::node::NODE_LEN)?).unwrap_or_default())
+    }
+}
+
+impl Dirstate {
+    pub fn read(repo_root: &Path) -> Result<Dirstate, HgError> {
+        let hg_dir = repo_root.join(".hg");
+        let dirstate_path = hg_dir.join("dirstate");
+        let data = match fs::read(&dirstate_path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Dirstate::default()),
+            Err(e) => return Err(e.into()),
+        };
+        if data.starts_with(V2_MARKER) {
+            Dirstate::parse_v2(&hg_dir, &dirstate_path, &data)
+        } else {
+            Dirstate::parse_v1(&dirstate_path, &data)
+        }
+    }
+
+    pub fn parse_v1(path: &Path, data: &[u8]) -> Result<Dirstate, HgError> {
+        let mut dirstate = Dirstate::default();
+        if data.is_empty() {
+            return Ok(dirstate);
+        }
+        let reader = Reader { path, data };
+        dirstate.parents = [reader.node(0)?, reader.node(20)?];
+        let mut pos = V1_HEADER_LEN;
+        while pos < data.len() {
+            let header = reader.bytes(pos, V1_ENTRY_HEADER_LEN)?;
+            let state =
+                EntryState::from_v1(header[0]).ok_or_else(|| reader.corrupt("unknown state"))?;
+            let name_len = reader.u32(pos + 13)? as usize;
+            let name = reader.bytes(pos + V1_ENTRY_HEADER_LEN, name_len)?;
+            let (name, copy_source) = match name.iter().position(|b| *b == 0) {
+                Some(nul) => (&name[..nul], Some(path_from_bytes(&name[nul + 1..]))),
+                None => (name, None),
+            };
+            dirstate.entries.insert(
+                path_from_bytes(name),
+                DirstateEntry {
+                    state,
+                    mode: reader.u32(pos + 1)?,
+                    size: reader.i32(pos + 5)?,
+                    mtime: reader.i32(pos + 9)?,
+                    mtime_nanos: 0,
+                    copy_source,
+                },
+            );
+            pos += V1_ENTRY_HEADER_LEN + name_len;
+        }
+        Ok(dirstate)
+    }
+
+    pub fn parse_v2(hg_dir: &Path, docket_path: &Path, docket: &[u8]) -> Result<Dirstate, HgError> {
+        let reader = Reader {
+            path: docket_path,
+            data: docket,
+        };
+        let mut offset = V2_MARKER.len();
+        let parents = [
+            reader.node(offset)?,
+            reader.node(offset + V2_STORED_NODE_LEN)?,
+        ];
+        offset += 2 * V2_STORED_NODE_LEN;
+        let root_start = reader.u32(offset)? as usize;
+        let root_len = reader.u32(offset + 4)? as usize;
+        offset += V2_TREE_METADATA_LEN;
+        let data_size = reader.u32(offset)? as usize;
+        let uuid_len = reader.bytes(offset + 4, 1)?[0] as usize;
+        let uuid = reader.bytes(V2_DOCKET_HEADER_LEN, uuid_len)?;
+        let uuid = std::str::from_utf8(uuid).map_err(|_| reader.corrupt("invalid uuid"))?;
+
+        let data_path = hg_dir.join(format!("dirstate.{}", uuid));
+        let data = fs::read(&data_path)?;
+        let data = data
+            .get(..data_size)
+            .ok_or_else(|| reader.corrupt("data file is shorter than recorded"))?;
+        let reader = Reader {
+            path: &data_path,
+            data,
+        };
+
+        let mut dirstate = Dirstate {
+            parents,
+            entries: BTreeMap::new(),
+        };
+        let mut pending = vec![(root_start, root_len)];
+        while let Some((start, len)) = pending.pop() {
+            for i in 0..len {
+                let node = start + i * V2_NODE_LEN;
+                let path_start = reader.u32(node)? as usize;
+                let path_len = reader.u16(node + 4)? as usize;
+                let copy_start = reader.u32(node + 8)? as usize;
+                let copy_len = reader.u16(node + 12)? as usize;
+                pending.push((
+                    reader.u32(node + 14)? as usize,
+                    reader.u32(node + 18)? as usize,
+                ));
+                let flags = reader.u16(node + 30)?;
+                if flags & (V2_WDIR_TRACKED | V2_P1_TRACKED | V2_P2_INFO) == 0 {
+                    continue;
+                }
+                let copy_source = match copy_start {
+                    0 => None,
+                    _ => Some(path_from_bytes(reader.bytes(copy_start, copy_len)?)),
+                };
+                let mut entry = v2_entry(flags, reader.u32(node + 32)?);
+                if flags & V2_HAS_MTIME != 0 && flags & V2_MTIME_SECOND_AMBIGUOUS == 0 {
+                    entry.mtime = reader.i32(node + 36)?;
+                    entry.mtime_nanos = reader.u32(node + 40)?;
+                }
+                entry.copy_source = copy_source;
+                dirstate
+                    .entries
+                    .insert(path_from_bytes(reader.bytes(path_start, path_len)?), entry);
+            }
+        }
+        Ok(dirstate)
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&DirstateEntry> {
+        self.entries.get(path)
+    }
+
+    pub fn is_merging(&self) -> bool {
+        !self.parents[1].is_null()
+    }
+}
+
+fn v2_entry(flags: u16, size: u32) -> DirstateEntry {
+    let wdir_tracked = flags & V2_WDIR_TRACKED != 0;
+    let p1_tracked = flags & V2_P1_TRACKED != 0;
+    let p2_info = flags & V2_P2_INFO != 0;
+    let has_mode_and_size = flags & V2_HAS_MODE_AND_SIZE != 0;
+    let mode = match (
+        flags & V2_MODE_IS_SYMLINK != 0,
+        flags & V2_MODE_EXEC_PERM != 0,
+    ) {
+        (true, _) => S_IFLNK | 0o777,
+        (false, true) => S_IFREG | 0o755,
+        (false, false) => S_IFREG | 0o644,
+    };
+    let mut entry = DirstateEntry {
+        state: EntryState::Normal,
+        mode: 0,
+        size: SIZE_NON_NORMAL,
+        mtime: MTIME_UNSET,
+        mtime_nanos: 0,
+        copy_source: None,
+    };
+    if !wdir_tracked {
+        entry.state = EntryState::Removed;
+        entry.size = match (p1_tracked, p2_info) {
+            (true, true) => SIZE_NON_NORMAL,
+            (false, true) => SIZE_FROM_OTHER_PARENT,
+            _ => 0,
+        };
+    } else if !p1_tracked && !p2_info {
+        entry.state = EntryState::Added;
+    } else if p1_tracked && p2_info {
+        entry.state = EntryState::Merged;
+        entry.size = SIZE_FROM_OTHER_PARENT;
+    } else if p2_info {
+        entry.mode = mode;
+        entry.size = SIZE_FROM_OTHER_PARENT;
+    } else if has_mode_and_size {
+        entry.mode = mode;
+        entry.size = (size & 0x7fff_ffff) as i32;
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_v2_docket() {
+        let root = std::env::temp_dir().join(format!("hgrs-dirstate-v2-{}", std::process::id()));
+        let hg_dir = root.join(".hg");
+        fs::create_dir_all(&hg_dir).unwrap();
+
+        let path = b"a.txt";
+        let mut data = vec![0u8; V2_NODE_LEN];
+        data[0..4].copy_from_slice(&(V2_NODE_LEN as u32).to_be_bytes());
+        data[4..6].copy_from_slice(&(path.len() as u16).to_be_bytes());
+        let flags = V2_WDIR_TRACKED | V2_P1_TRACKED | V2_HAS_MODE_AND_SIZE | V2_MODE_EXEC_PERM;
+        data[30..32].copy_from_slice(&flags.to_be_bytes());
+        data[32..36].copy_from_slice(&12u32.to_be_bytes());
+        data.extend_from_slice(path);
+        fs::write(hg_dir.join("dirstate.0123abcd"), &data).unwrap();
+
+        let mut docket = b"dirstate-v2\n".to_vec();
+        docket.extend_from_slice(&[0x11; 20]);
+        docket.extend_from_slice(&[0; 12]);
+        docket.extend_from_slice(&[0; V2_STORED_NODE_LEN]);
+        let mut metadata = [0u8; V2_TREE_METADATA_LEN];
+        metadata[4..8].copy_from_slice(&1u32.to_be_bytes());
+        docket.extend_from_slice(&metadata);
+        docket.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        docket.push(8);
+        docket.extend_from_slice(b"0123abcd");
+        fs::write(hg_dir.join("dirstate"), &docket).unwrap();
+
+        let dirstate = Dirstate::read(&root);
+        fs::remove_dir_all(&root).unwrap();
+        let dirstate = dirstate.unwrap();
+        assert_eq!(
+            dirstate.parents[0],
+            NodeId::from_bytes(&[0x11; 20]).unwrap()
+        );
+        assert!(dirstate.parents[1].is_null());
+        let entry = dirstate.get(Path::new("a.txt")).unwrap();
+        assert_eq!(entry.state, EntryState::Normal);
+        assert_eq!(entry.size, 12);
+        assert!(entry.is_exec());
+    }
+}
@@ -0,0 +1,68 @@
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum HgError {
+    BinaryNotFound,
+    CommandFailed { code: Option<i32>, stderr: String },
+    NothingChanged,
+    NoFiles,
+    Parse { line: String },
+    PathOutsideRepo(PathBuf),
+    UnknownFile(PathBuf),
+    UnknownStatus(char),
+    Corrupt { path: PathBuf, reason: String },
+    Config { origin: String, reason: String },
+    Unsupported(String),
+    RepositoryExists(PathBuf),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for HgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HgError::BinaryNotFound => write!(f, "hg not found"),
+            HgError::CommandFailed {
+                code: Some(code),
+                stderr,
+            } => {
+                write!(f, "hg exited with code {}: {}", code, stderr.trim_end())
+            }
+            HgError::CommandFailed { code: None, stderr } => {
+                write!(f, "hg was terminated by a signal: {}", stderr.trim_end())
+            }
+            HgError::NothingChanged => write!(f, "nothing changed"),
+            HgError::NoFiles => write!(f, "no files given"),
+            HgError::Parse { line } => write!(f, "Unable to parse hg output: {:?}", line),
+            HgError::PathOutsideRepo(path) => {
+                write!(f, "{} is outside the repository", path.display())
+            }
+            HgError::UnknownFile(path) => write!(f, "{} is not known to hg", path.display()),
+            HgError::UnknownStatus(status) => write!(f, "Unknown status: {}", status),
+            HgError::Corrupt { path, reason } => {
+                write!(f, "{} is corrupt: {}", path.display(), reason)
+            }
+            HgError::Config { origin, reason } => write!(f, "{}: parse error: {}", origin, reason),
+            HgError::Unsupported(what) => write!(f, "Unsupported operation: {}", what),
+            HgError::RepositoryExists(path) => {
+                write!(f, "repository {} already exists", path.display())
+            }
+            HgError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for HgError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HgError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for HgError {
+    fn from(value: std::io::Error) -> Self {
+        HgError::Io(value)
+    }
+}
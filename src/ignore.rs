@@ -0,0 +1,280 @@
+use crate::config::{expand_path, Config};
+use crate::error::HgError;
+use log::warn;
+use regex::{Regex, RegexSet};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+const GLOB_SUFFIX: &str = "(?:/|$)";
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Syntax {
+    RelRegexp,
+    RelGlob,
+    RootGlob,
+    Include,
+    SubInclude,
+}
+
+impl Syntax {
+    fn from_name(name: &str) -> Option<Syntax> {
+        match name {
+            "re" | "regexp" | "relre" => Some(Syntax::RelRegexp),
+            "glob" | "relglob" => Some(Syntax::RelGlob),
+            "rootglob" => Some(Syntax::RootGlob),
+            "include" => Some(Syntax::Include),
+            "subinclude" => Some(Syntax::SubInclude),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Scope {
+    prefix: String,
+    patterns: RegexSet,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreMatcher {
+    scopes: Vec<Scope>,
+}
+
+impl IgnoreMatcher {
+    pub fn from_repo(root: &Path) -> Result<IgnoreMatcher, HgError> {
+        IgnoreMatcher::from_config(root, &Config::load(Some(root))?)
+    }
+
+    pub fn from_config(root: &Path, config: &Config) -> Result<IgnoreMatcher, HgError> {
+        let mut files = vec![root.join(".hgignore")];
+        files.extend(
+            config
+                .items("ui")
+                .filter(|(key, _)| *key == "ignore" || key.starts_with("ignore."))
+                .map(|(_, file)| root.join(expand_path(&file.value))),
+        );
+        IgnoreMatcher::from_files(root, &files)
+    }
+
+    pub fn from_files(root: &Path, files: &[PathBuf]) -> Result<IgnoreMatcher, HgError> {
+        let mut matcher = IgnoreMatcher::default();
+        let mut seen = HashSet::new();
+        let mut patterns = vec![];
+        for file in files {
+            if file.exists() {
+                matcher.load(root, file, &mut patterns, &mut seen)?;
+            } else if *file != root.join(".hgignore") {
+                warn!("skipping unreadable pattern file {}", file.display());
+            }
+        }
+        matcher.push_scope(String::new(), patterns);
+        Ok(matcher)
+    }
+
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let path = hg_path(path);
+        self.scopes.iter().any(|scope| {
+            let relative = match scope.prefix.as_str() {
+                "" => path.as_str(),
+                prefix => match path.strip_prefix(prefix).and_then(|p| p.strip_prefix('/')) {
+                    Some(relative) => relative,
+                    None => return false,
+                },
+            };
+            scope.patterns.is_match(relative)
+                || relative
+                    .match_indices('/')
+                    .any(|(i, _)| scope.patterns.is_match(&relative[..i]))
+        })
+    }
+
+    fn load(
+        &mut self,
+        root: &Path,
+        file: &Path,
+        patterns: &mut Vec<String>,
+        seen: &mut HashSet<PathBuf>,
+    ) -> Result<(), HgError> {
+        if !seen.insert(file.to_path_buf()) {
+            return Ok(());
+        }
+        let content = match fs::read(file) {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("skipping unreadable pattern file {}: {}", file.display(), e);
+                return Ok(());
+            }
+        };
+        let dir = file.parent().unwrap_or(root);
+        let mut syntax = Syntax::RelRegexp;
+        for line in String::from_utf8_lossy(&content).lines() {
+            let line = strip_comment(line);
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix("syntax:") {
+                match Syntax::from_name(name.trim()) {
+                    Some(s) => syntax = s,
+                    None => warn!("{}: ignoring invalid syntax {:?}", file.display(), name),
+                }
+                continue;
+            }
+            let (line_syntax, pattern) = match line.split_once(':') {
+                Some((name, rest)) => match Syntax::from_name(name) {
+                    Some(s) => (s, rest),
+                    None => (syntax, line),
+                },
+                None => (syntax, line),
+            };
+            match line_syntax {
+                Syntax::Include => self.load(root, &dir.join(pattern), patterns, seen)?,
+                Syntax::SubInclude => {
+                    let target = dir.join(pattern);
+                    let prefix = target
+                        .parent()
+                        .and_then(|p| p.strip_prefix(root).ok())
+                        .map(hg_path)
+                        .unwrap_or_default();
+                    let mut sub_patterns = vec![];
+                    self.load(root, &target, &mut sub_patterns, seen)?;
+                    self.push_scope(prefix, sub_patterns);
+                }
+                _ => {
+                    let regex = to_regex(line_syntax, pattern);
+                    match Regex::new(&regex) {
+                        Ok(_) => patterns.push(regex),
+                        Err(e) => warn!("{}: skipping pattern {:?}: {}", file.display(), line, e),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn push_scope(&mut self, prefix: String, patterns: Vec<String>) {
+        if patterns.is_empty() {
+            return;
+        }
+        match RegexSet::new(&patterns) {
+            Ok(set) => self.scopes.push(Scope {
+                prefix,
+                patterns: set,
+            }),
+            Err(e) => warn!("skipping ignore patterns under {:?}: {}", prefix, e),
+        }
+    }
+}
+
+fn hg_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn strip_comment(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('#') => out.push('#'),
+                Some(next) => {
+                    out.push('\\');
+                    out.push(next);
+                }
+                None => out.push('\\'),
+            },
+            '#' => break,
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn to_regex(syntax: Syntax, pattern: &str) -> String {
+    let regex = match syntax {
+        Syntax::RelRegexp if pattern.starts_with('^') => pattern.to_string(),
+        Syntax::RelRegexp => format!(".*{}", pattern),
+        Syntax::RootGlob => format!("{}{}", glob_to_regex(pattern), GLOB_SUFFIX),
+        _ => {
+            let glob = glob_to_regex(pattern);
+            match glob.strip_prefix("[^/]*") {
+                Some(rest) => format!(".*{}{}", rest, GLOB_SUFFIX),
+                None => format!("(?:|.*/){}{}", glob, GLOB_SUFFIX),
+            }
+        }
+    };
+    format!("^(?:{})", regex)
+}
+
+fn glob_to_regex(pattern: &str) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut res = String::new();
+    let mut group = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        match c {
+            '*' if chars.get(i) == Some(&'*') => {
+                i += 1;
+                if chars.get(i) == Some(&'/') {
+                    i += 1;
+                    res.push_str("(?:.*/)?");
+                } else {
+                    res.push_str(".*");
+                }
+            }
+            '*' => res.push_str("[^/]*"),
+            '?' => res.push('.'),
+            '[' => {
+                let mut j = i;
+                if matches!(chars.get(j), Some('!') | Some(']')) {
+                    j += 1;
+                }
+                while j < chars.len() && chars[j] != ']' {
+                    j += 1;
+                }
+                if j >= chars.len() {
+                    res.push_str("\\[");
+                    continue;
+                }
+                res.push('[');
+                for (k, ch) in chars[i..j].iter().enumerate() {
+                    match ch {
+                        '!' if k == 0 => res.push('^'),
+                        '^' if k == 0 => res.push_str("\\^"),
+                        '\\' | '[' | '&' | '~' => {
+                            res.push('\\');
+                            res.push(*ch);
+                        }
+                        _ => res.push(*ch),
+                    }
+                }
+                res.push(']');
+                i = j + 1;
+            }
+            '{' => {
+                group += 1;
+                res.push_str("(?:");
+            }
+            '}' if group > 0 => {
+                group -= 1;
+                res.push(')');
+            }
+            ',' if group > 0 => res.push('|'),
+            '\\' if i < chars.len() => {
+                res.push_str(&regex::escape(&chars[i].to_string()));
+                i += 1;
+            }
+            _ => res.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    res
+}
@@ -0,0 +1,66 @@
+use crate::error::HgError;
+use crate::{MercurialRepository, RepositoryBuilder};
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+const REQUIREMENTS: &str = "dotencode\nfncache\ngeneraldelta\nrevlogv1\nsparserevlog\nstore\n";
+
+/// Written by hg so that clients predating the store layout refuse the repository.
+const DUMMY_CHANGELOG: &[u8] = b"\0\0\0\x02 dummy changelog to prevent using the old repo layout";
+
+#[derive(Debug, Clone, Default)]
+pub struct CloneOptions {
+    pub rev: Option<String>,
+    pub branch: Option<String>,
+    pub no_update: bool,
+    pub uncompressed: bool,
+    pub pull: bool,
+}
+
+/// Creates an empty repository the way `hg init` does, without running hg.
+pub(crate) fn init_native(path: &Path) -> Result<(), HgError> {
+    let hg_dir = path.join(".hg");
+    if hg_dir.exists() {
+        return Err(HgError::RepositoryExists(path.to_path_buf()));
+    }
+    fs::create_dir_all(hg_dir.join("store"))?;
+    fs::write(hg_dir.join("00changelog.i"), DUMMY_CHANGELOG)?;
+    fs::write(hg_dir.join("requires"), REQUIREMENTS)?;
+    Ok(())
+}
+
+pub(crate) fn clone_args(source: &str, dest: &Path, opts: &CloneOptions) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec!["clone".into()];
+    if let Some(rev) = &opts.rev {
+        args.extend(["-r".into(), rev.into()]);
+    }
+    if let Some(branch) = &opts.branch {
+        args.extend(["-b".into(), branch.into()]);
+    }
+    if opts.no_update {
+        args.push("--noupdate".into());
+    }
+    if opts.uncompressed {
+        args.push("--uncompressed".into());
+    }
+    if opts.pull {
+        args.push("--pull".into());
+    }
+    args.extend(["--".into(), source.into(), dest.into()]);
+    args
+}
+
+impl MercurialRepository {
+    pub fn init(path: &Path) -> Result<MercurialRepository, HgError> {
+        RepositoryBuilder::new(path).init()
+    }
+
+    pub fn clone(
+        source: &str,
+        dest: &Path,
+        opts: &CloneOptions,
+    ) -> Result<MercurialRepository, HgError> {
+        RepositoryBuilder::new(dest).clone_from(source, opts)
+    }
+}
@@ -1,20 +1,77 @@
+mod annotate;
+mod backend;
+mod bookmark;
+mod branch;
+mod builder;
+mod cat;
+mod changeset;
+mod cmdserver;
+mod commit;
+mod config;
+mod diff;
+mod dirstate;
+mod error;
+mod ignore;
+mod init;
+mod manifest;
 mod mercurial_file;
+mod node;
+mod phase;
+mod remote;
+mod revlog;
+mod revset;
+mod status;
+mod store;
+mod tags;
+mod working_copy;
 
-pub use crate::mercurial_file::FileStatus;
-use crate::mercurial_file::MercurialFile;
-use log::debug;
-use std::path::PathBuf;
-use std::process::Command;
+pub use crate::annotate::{AnnotateOptions, AnnotatedLine};
+use crate::backend::checked;
+pub use crate::backend::{CliBackend, CommandServerBackend, HgBackend, NativeBackend};
+pub use crate::bookmark::Bookmark;
+pub use crate::branch::{Branch, BranchState};
+pub use crate::builder::RepositoryBuilder;
+pub use crate::cat::FileContent;
+pub use crate::changeset::{Changeset, LogOptions};
+pub use crate::cmdserver::{CommandOutput, CommandServer};
+pub use crate::commit::CommitOptions;
+pub use crate::config::{Config, ConfigValue};
+pub use crate::diff::{DiffLine, FileDiff, Hunk, LineTag};
+pub use crate::dirstate::{Dirstate, DirstateEntry, EntryState};
+pub use crate::error::HgError;
+pub use crate::ignore::IgnoreMatcher;
+pub use crate::init::CloneOptions;
+pub use crate::manifest::{FileFlags, Manifest, ManifestDirEntry, ManifestEntry};
+pub use crate::mercurial_file::{FileStatus, MercurialFile};
+pub use crate::node::NodeId;
+pub use crate::phase::Phase;
+pub use crate::remote::{PullResult, PushResult};
+pub use crate::revlog::{IndexEntry, Revision, Revlog, NULL_REVISION};
+pub use crate::revset::Revset;
+pub use crate::status::native_status;
+use crate::status::native_status_with;
+pub use crate::store::Store;
+pub use crate::tags::Tag;
+pub use crate::working_copy::WorkingCopyInfo;
+use log::{debug, warn};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct MercurialRepository {
-    fail: bool,
     path: PathBuf,
     files: Vec<MercurialFile>,
+    ignore: IgnoreMatcher,
+    backend: Arc<dyn HgBackend>,
+    hgrcpath: Option<OsString>,
+    config_overrides: Vec<(String, String, String)>,
+    /// One `<code> <path>` row per file, without copy sources, so each row
+    /// can be parsed on its own with `MercurialFile::parse`.
     pub raw_statuses: Vec<String>,
 }
 
-pub fn is_mercurial_repository(path: &PathBuf) -> bool {
+pub fn is_mercurial_repository(path: &Path) -> bool {
     if !path.exists() {
         return false;
     }
@@ -27,13 +84,13 @@ pub fn is_mercurial_repository(path: &PathBuf) -> bool {
     true
 }
 
-pub fn find_repo_recursively(path: &PathBuf, mut depth_max: u32) -> Option<MercurialRepository> {
+pub fn find_repo_recursively(path: &Path, mut depth_max: u32) -> Option<MercurialRepository> {
     if is_mercurial_repository(path) {
         return Some(MercurialRepository::new(path));
     }
-    let mut p = path.clone();
+    let mut p = path.to_path_buf();
     while depth_max > 0 {
-        p = p.parent().unwrap().to_path_buf();
+        p = p.parent()?.to_path_buf();
         if is_mercurial_repository(&p) {
             return Some(MercurialRepository::new(&p));
         }
@@ -42,77 +99,112 @@ pub fn find_repo_recursively(path: &PathBuf, mut depth_max: u32) -> Option<Mercu
     None
 }
 
+fn split_rows(output: &[u8]) -> Result<Vec<String>, HgError> {
+    output
+        .split(|b| *b == b'\n')
+        .map(|row| {
+            String::from_utf8(row.to_vec()).map_err(|_| HgError::Parse {
+                line: String::from_utf8_lossy(row).into_owned(),
+            })
+        })
+        .collect()
+}
+
 impl MercurialRepository {
-    pub fn new(path_buf: &PathBuf) -> MercurialRepository {
-        let fail = match Command::new("which")
-            .arg("hg")
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .status()
-        {
-            Ok(s) if s.success() => false,
-            _ => true,
-        };
-        if fail {
-            panic!("hg not found");
-        };
-        let raw_statuses = Command::new("hg")
-            .current_dir(path_buf)
-            .arg("status")
-            .arg("--all")
-            .output()
-            .unwrap()
-            .stdout;
-        let statuses = String::from_utf8(raw_statuses).unwrap();
-        let rows: Vec<_> = statuses.split('\n').collect();
-        let mut repo = MercurialRepository {
-            fail,
-            path: (*(path_buf.clone())).to_owned(),
-            files: vec![],
-            raw_statuses: rows.iter().map(|s| s.to_string()).collect(),
-        };
-        repo.set_files();
-        repo
+    pub fn new(path_buf: &Path) -> MercurialRepository {
+        MercurialRepository::try_new(path_buf).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_new(path_buf: &Path) -> Result<MercurialRepository, HgError> {
+        RepositoryBuilder::new(path_buf).build()
+    }
+
+    pub fn builder(path_buf: &Path) -> RepositoryBuilder {
+        RepositoryBuilder::new(path_buf)
+    }
+
+    fn hg_output<S: AsRef<OsStr>>(&self, args: &[S]) -> Result<CommandOutput, HgError> {
+        let args: Vec<OsString> = args.iter().map(|a| a.as_ref().to_owned()).collect();
+        self.backend.run_command(&args)
+    }
+
+    fn hg<S: AsRef<OsStr>>(&self, args: &[S]) -> Result<Vec<u8>, HgError> {
+        checked(self.hg_output(args)?)
+    }
+
+    fn relative_path<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(&self.path).unwrap_or(path)
     }
 
-    fn set_files(&mut self) {
-        self.files = self
-            .raw_statuses
-            .clone()
+    fn path_arg(&self, path: &Path) -> OsString {
+        self.backend.path_arg(self.relative_path(path))
+    }
+
+    fn read_statuses(&self) -> Result<(Vec<String>, Vec<MercurialFile>), HgError> {
+        let files = match self.backend.status() {
+            Ok(files) => files,
+            Err(HgError::BinaryNotFound) => {
+                debug!("hg not found, reading the dirstate directly");
+                let ignore = IgnoreMatcher::from_config(&self.path, &self.config()?)?;
+                native_status_with(&self.path, &ignore)?
+            }
+            Err(e) => return Err(e),
+        };
+        let mut rows: Vec<String> = files
             .iter()
-            .filter(|s| !s.is_empty())
-            .map(|r| MercurialFile::from(r.to_string()))
+            .filter_map(|f| Some(format!("{} {}", f.status.code()?, f.path.display())))
             .collect();
+        rows.push(String::new());
+        Ok((rows, files))
+    }
+
+    pub fn files(&self) -> &[MercurialFile] {
+        &self.files
     }
 
     pub fn update_statuses(&mut self) {
-        let raw_statuses = Command::new("hg")
-            .current_dir(&self.path)
-            .arg("status")
-            .arg("--all")
-            .output()
-            .unwrap()
-            .stdout;
-        let statuses = String::from_utf8(raw_statuses).unwrap();
-        let rows: Vec<_> = statuses.split('\n').collect();
-        self.raw_statuses = rows.iter().map(|s| s.to_string()).collect();
-        self.set_files()
-    }
-
-    pub fn get_status(&self, file_name: &PathBuf) -> FileStatus {
-        if self.fail {
-            panic!("hg not found");
-        }
-        let name = file_name.strip_prefix(&self.path).unwrap();
+        self.try_update_statuses()
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_update_statuses(&mut self) -> Result<(), HgError> {
+        (self.raw_statuses, self.files) = self.read_statuses()?;
+        // Only `is_ignored` relies on the cached matcher; statuses from hg do not.
+        self.ignore = self
+            .config()
+            .and_then(|config| IgnoreMatcher::from_config(&self.path, &config))
+            .unwrap_or_else(|e| {
+                warn!("unable to load ignore patterns: {}", e);
+                IgnoreMatcher::default()
+            });
+        Ok(())
+    }
+
+    pub fn get_status(&self, file_name: &Path) -> FileStatus {
+        self.try_get_status(file_name)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_get_status(&self, file_name: &Path) -> Result<FileStatus, HgError> {
+        let name = file_name
+            .strip_prefix(&self.path)
+            .map_err(|_| HgError::PathOutsideRepo(file_name.to_path_buf()))?;
         if file_name.is_dir() {
             debug!("{} is a directory", name.display());
-            return FileStatus::Directory;
+            return Ok(FileStatus::Directory);
         }
         self.files
             .iter()
             .find(|f| f.path == name)
-            .unwrap()
-            .status
-            .clone()
+            .map(|f| f.status)
+            .ok_or_else(|| HgError::UnknownFile(name.to_path_buf()))
+    }
+
+    pub fn is_ignored(&self, file_name: &Path) -> bool {
+        match file_name.strip_prefix(&self.path) {
+            Ok(name) => self.ignore.is_ignored(name),
+            Err(_) if file_name.is_relative() => self.ignore.is_ignored(file_name),
+            Err(_) => false,
+        }
     }
 }
@@ -0,0 +1,210 @@
+use crate::dirstate::Dirstate;
+use crate::error::HgError;
+use crate::mercurial_file::path_from_bytes;
+use crate::node::NodeId;
+use crate::revlog::Revision;
+use crate::revset::Revset;
+use crate::store::{manifest_lines, Store};
+use crate::MercurialRepository;
+use log::debug;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum FileFlags {
+    #[default]
+    Regular,
+    Executable,
+    Symlink,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ManifestEntry {
+    pub path: PathBuf,
+    pub node: NodeId,
+    pub flags: FileFlags,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ManifestDirEntry<'a> {
+    File(&'a ManifestEntry),
+    Directory(PathBuf),
+}
+
+/// Entries are kept sorted by path components so that every directory maps
+/// to a contiguous range, which is what `list_dir` relies on.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Manifest {
+    entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    fn new(mut entries: Vec<ManifestEntry>) -> Manifest {
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Manifest { entries }
+    }
+
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ManifestEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&ManifestEntry> {
+        self.entries
+            .binary_search_by(|e| e.path.as_path().cmp(path))
+            .ok()
+            .map(|i| &self.entries[i])
+    }
+
+    pub fn list_dir(&self, dir: &Path) -> Vec<ManifestDirEntry<'_>> {
+        let start = self.entries.partition_point(|e| e.path.as_path() < dir);
+        let mut listing: Vec<ManifestDirEntry> = vec![];
+        for entry in self.entries[start..]
+            .iter()
+            .take_while(|e| e.path.starts_with(dir))
+        {
+            let mut rest = entry
+                .path
+                .strip_prefix(dir)
+                .unwrap_or(&entry.path)
+                .components();
+            let Some(Component::Normal(name)) = rest.next() else {
+                continue;
+            };
+            if rest.next().is_none() {
+                listing.push(ManifestDirEntry::File(entry));
+                continue;
+            }
+            let subdir = dir.join(name);
+            if !matches!(listing.last(), Some(ManifestDirEntry::Directory(d)) if *d == subdir) {
+                listing.push(ManifestDirEntry::Directory(subdir));
+            }
+        }
+        listing
+    }
+}
+
+impl<'a> IntoIterator for &'a Manifest {
+    type Item = &'a ManifestEntry;
+    type IntoIter = std::slice::Iter<'a, ManifestEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+pub(crate) fn parse_manifest_line(path: &[u8], value: &[u8]) -> Result<ManifestEntry, HgError> {
+    let parse_error = || HgError::Parse {
+        line: format!(
+            "{}\0{}",
+            String::from_utf8_lossy(path),
+            String::from_utf8_lossy(value)
+        ),
+    };
+    let hex = value.get(..40).ok_or_else(parse_error)?;
+    let node = std::str::from_utf8(hex)
+        .map_err(|_| parse_error())?
+        .parse()?;
+    let flags = match &value[40..] {
+        b"" => FileFlags::Regular,
+        b"x" => FileFlags::Executable,
+        b"l" => FileFlags::Symlink,
+        _ => return Err(parse_error()),
+    };
+    Ok(ManifestEntry {
+        path: path_from_bytes(path),
+        node,
+        flags,
+    })
+}
+
+fn parse_debug_manifest(output: &[u8]) -> Result<Vec<ManifestEntry>, HgError> {
+    let text = String::from_utf8_lossy(output);
+    text.lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let parse_error = || HgError::Parse {
+                line: line.to_string(),
+            };
+            let (node, rest) = line.split_once(' ').ok_or_else(parse_error)?;
+            let (_mode, rest) = rest.split_once(' ').ok_or_else(parse_error)?;
+            let mut chars = rest.chars();
+            let flags = match chars.next() {
+                Some('*') => FileFlags::Executable,
+                Some('@') => FileFlags::Symlink,
+                Some(' ') => FileFlags::Regular,
+                _ => return Err(parse_error()),
+            };
+            let path = chars.as_str().strip_prefix(' ').ok_or_else(parse_error)?;
+            Ok(ManifestEntry {
+                path: PathBuf::from(path),
+                node: node.parse()?,
+                flags,
+            })
+        })
+        .collect()
+}
+
+/// Resolves plain identifiers (revision numbers, full nodes, `.`, `tip` and
+/// `null`) from the store, returning `None` for anything that needs hg.
+pub(crate) fn resolve_native(root: &Path, rev: &Revset) -> Result<Option<NodeId>, HgError> {
+    let expr = rev.to_string();
+    let changelog = Store::open(root)?.changelog()?;
+    let number = expr
+        .strip_prefix("rev(")
+        .and_then(|r| r.strip_suffix(')'))
+        .unwrap_or(&expr);
+    if let Ok(number) = number.parse::<Revision>() {
+        if (0..=changelog.tip()).contains(&number) {
+            return changelog.node(number).map(Some);
+        }
+    }
+    match expr.as_str() {
+        "." => return Ok(Some(Dirstate::read(root)?.parents[0])),
+        "null" => return Ok(Some(NodeId::NULL)),
+        "tip" if changelog.is_empty() => return Ok(Some(NodeId::NULL)),
+        "tip" => return changelog.node(changelog.tip()).map(Some),
+        _ => {}
+    }
+    Ok(expr
+        .parse::<NodeId>()
+        .ok()
+        .filter(|node| changelog.rev(node).is_some()))
+}
+
+impl MercurialRepository {
+    pub(crate) fn resolve_node(&self, rev: &Revset) -> Result<NodeId, HgError> {
+        if let Some(node) = resolve_native(&self.path, rev)? {
+            return Ok(node);
+        }
+        let expr = rev.to_string();
+        let output = self.hg(&["log", "-r", &expr, "-l", "1", "-T", "{node}"])?;
+        String::from_utf8_lossy(&output).trim().parse()
+    }
+
+    pub fn manifest(&self, rev: impl Into<Revset>) -> Result<Manifest, HgError> {
+        let rev = rev.into();
+        let store = Store::open(&self.path)?;
+        if store.has_requirement("treemanifest") {
+            debug!("tree manifests are not parsed natively, asking hg");
+            let rev = rev.to_string();
+            let output = self.hg(&["manifest", "--debug", "-r", &rev])?;
+            return Ok(Manifest::new(parse_debug_manifest(&output)?));
+        }
+        let text = store.manifest_text(&self.resolve_node(&rev)?)?;
+        let entries = manifest_lines(&text)
+            .map(|(path, value)| parse_manifest_line(path, value))
+            .collect::<Result<_, _>>()?;
+        Ok(Manifest::new(entries))
+    }
+}
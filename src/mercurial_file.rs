@@ -1,46 +1,130 @@
+use crate::error::HgError;
+use std::collections::HashSet;
+use std::fmt;
 use std::path::PathBuf;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
 pub enum FileStatus {
     Modified,
     Added,
+    Renamed,
+    Copied,
     Removed,
     Clean,
     Missing,
+    #[default]
     NotTracked,
     Ignored,
     Directory,
 }
 
-impl Default for FileStatus {
-    fn default() -> Self {
-        FileStatus::NotTracked
-    }
-}
-
 #[derive(Debug, Clone)]
 pub struct MercurialFile {
     pub path: PathBuf,
     pub status: FileStatus,
+    pub copied_from: Option<PathBuf>,
+}
+
+impl FileStatus {
+    pub fn from_code(code: char) -> Result<FileStatus, HgError> {
+        match code {
+            'M' => Ok(FileStatus::Modified),
+            'A' => Ok(FileStatus::Added),
+            'R' => Ok(FileStatus::Removed),
+            'C' => Ok(FileStatus::Clean),
+            '!' => Ok(FileStatus::Missing),
+            '?' => Ok(FileStatus::NotTracked),
+            'I' => Ok(FileStatus::Ignored),
+            _ => Err(HgError::UnknownStatus(code)),
+        }
+    }
+
+    pub fn code(&self) -> Option<char> {
+        match self {
+            FileStatus::Modified => Some('M'),
+            FileStatus::Added | FileStatus::Renamed | FileStatus::Copied => Some('A'),
+            FileStatus::Removed => Some('R'),
+            FileStatus::Clean => Some('C'),
+            FileStatus::Missing => Some('!'),
+            FileStatus::NotTracked => Some('?'),
+            FileStatus::Ignored => Some('I'),
+            FileStatus::Directory => None,
+        }
+    }
+}
+
+impl MercurialFile {
+    pub fn parse(line: &str) -> Result<MercurialFile, HgError> {
+        let mut chars = line.chars();
+        let (Some(status), Some(' ')) = (chars.next(), chars.next()) else {
+            return Err(HgError::Parse {
+                line: line.to_string(),
+            });
+        };
+        Ok(MercurialFile {
+            path: PathBuf::from(chars.as_str()),
+            status: FileStatus::from_code(status)?,
+            copied_from: None,
+        })
+    }
+
+    pub fn parse_status(rows: &[String]) -> Result<Vec<MercurialFile>, HgError> {
+        let mut files: Vec<MercurialFile> = vec![];
+        for row in rows.iter().filter(|r| !r.is_empty()) {
+            match (row.strip_prefix("  "), files.last_mut()) {
+                (Some(source), Some(previous)) => {
+                    previous.copied_from = Some(PathBuf::from(source));
+                }
+                _ => files.push(MercurialFile::parse(row)?),
+            }
+        }
+        classify_copies(&mut files);
+        Ok(files)
+    }
+}
+
+pub(crate) fn classify_copies(files: &mut [MercurialFile]) {
+    let removed: HashSet<PathBuf> = files
+        .iter()
+        .filter(|f| f.status == FileStatus::Removed)
+        .map(|f| f.path.clone())
+        .collect();
+    for file in files.iter_mut().filter(|f| f.status == FileStatus::Added) {
+        if let Some(source) = &file.copied_from {
+            file.status = match removed.contains(source) {
+                true => FileStatus::Renamed,
+                false => FileStatus::Copied,
+            };
+        }
+    }
 }
 
 impl From<String> for MercurialFile {
     fn from(value: String) -> Self {
-        let status = value.chars().nth(0).unwrap();
-        let path: String = value.chars().skip(2).collect();
-
-        MercurialFile {
-            path: PathBuf::from(path),
-            status: match status {
-                'M' => FileStatus::Modified,
-                'A' => FileStatus::Added,
-                'R' => FileStatus::Removed,
-                'C' => FileStatus::Clean,
-                '!' => FileStatus::Missing,
-                '?' => FileStatus::NotTracked,
-                'I' => FileStatus::Ignored,
-                _ => panic!("Unknown status: {}", status),
-            },
+        MercurialFile::parse(&value).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl fmt::Display for MercurialFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status.code() {
+            Some(code) => write!(f, "{} {}", code, self.path.display())?,
+            None => write!(f, "{}", self.path.display())?,
+        }
+        match &self.copied_from {
+            Some(source) => write!(f, "\n  {}", source.display()),
+            None => Ok(()),
         }
     }
 }
+
+#[cfg(unix)]
+pub(crate) fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
@@ -0,0 +1,51 @@
+use crate::error::HgError;
+use std::fmt;
+use std::str::FromStr;
+
+pub const NODE_LEN: usize = 20;
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct NodeId(pub [u8; NODE_LEN]);
+
+impl NodeId {
+    pub const NULL: NodeId = NodeId([0; NODE_LEN]);
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<NodeId> {
+        Some(NodeId(bytes.get(..NODE_LEN)?.try_into().ok()?))
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == NodeId::NULL
+    }
+
+    pub fn short(&self) -> String {
+        self.to_string()[..12].to_string()
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for NodeId {
+    type Err = HgError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_error = || HgError::Parse {
+            line: s.to_string(),
+        };
+        if s.len() != NODE_LEN * 2 || !s.is_ascii() {
+            return Err(parse_error());
+        }
+        let mut node = [0; NODE_LEN];
+        for (i, byte) in node.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).map_err(|_| parse_error())?;
+        }
+        Ok(NodeId(node))
+    }
+}
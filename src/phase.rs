@@ -0,0 +1,133 @@
+use crate::error::HgError;
+use crate::node::NodeId;
+use crate::revlog::{Revision, NULL_REVISION};
+use crate::revset::Revset;
+use crate::store::Store;
+use crate::MercurialRepository;
+use log::debug;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Phase {
+    #[default]
+    Public,
+    Draft,
+    Secret,
+}
+
+impl Phase {
+    pub fn is_mutable(&self) -> bool {
+        *self != Phase::Public
+    }
+
+    fn from_number(number: u32) -> Phase {
+        match number {
+            0 => Phase::Public,
+            1 => Phase::Draft,
+            // archived and internal changesets are hidden, treat them like secret ones
+            _ => Phase::Secret,
+        }
+    }
+
+    fn flag(&self) -> &'static str {
+        match self {
+            Phase::Public => "--public",
+            Phase::Draft => "--draft",
+            Phase::Secret => "--secret",
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Phase::Public => "public",
+            Phase::Draft => "draft",
+            Phase::Secret => "secret",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Phase {
+    type Err = HgError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "public" => Ok(Phase::Public),
+            "draft" => Ok(Phase::Draft),
+            "secret" | "archived" | "internal" => Ok(Phase::Secret),
+            _ => Err(HgError::Parse {
+                line: s.to_string(),
+            }),
+        }
+    }
+}
+
+impl MercurialRepository {
+    pub fn phase(&self, rev: impl Into<Revset>) -> Result<Phase, HgError> {
+        let rev = rev.into().to_string();
+        let output = self.hg(&["log", "-r", &rev, "-l", "1", "-T", "{phase}"])?;
+        String::from_utf8_lossy(&output).parse()
+    }
+
+    /// Reads `phaseroots` and propagates each root's phase to its descendants.
+    /// Repositories without the file only contain public changesets.
+    pub fn phases(&self) -> Result<BTreeMap<Revision, Phase>, HgError> {
+        let store = Store::open(&self.path)?;
+        let changelog = store.changelog()?;
+        let content = match fs::read_to_string(store.root().join("phaseroots")) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut roots: BTreeMap<Revision, Phase> = BTreeMap::new();
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            let parse_error = || HgError::Parse {
+                line: line.to_string(),
+            };
+            let (number, node) = line.split_once(' ').ok_or_else(parse_error)?;
+            let phase = Phase::from_number(number.parse().map_err(|_| parse_error())?);
+            let node: NodeId = node.trim().parse()?;
+            match changelog.rev(&node) {
+                Some(rev) => {
+                    let root = roots.entry(rev).or_default();
+                    *root = (*root).max(phase);
+                }
+                None => debug!("ignoring phase root for unknown node {}", node),
+            }
+        }
+
+        let mut phases = BTreeMap::new();
+        for rev in 0..=changelog.tip() {
+            let mut phase = roots.get(&rev).copied().unwrap_or_default();
+            for parent in changelog.parents(rev)? {
+                if parent != NULL_REVISION {
+                    phase = phase.max(phases[&parent]);
+                }
+            }
+            phases.insert(rev, phase);
+        }
+        Ok(phases)
+    }
+
+    pub fn set_phase(
+        &mut self,
+        revs: impl Into<Revset>,
+        phase: Phase,
+        force: bool,
+    ) -> Result<(), HgError> {
+        let mut args: Vec<OsString> = vec!["phase".into(), phase.flag().into()];
+        if force {
+            args.push("--force".into());
+        }
+        args.extend(["-r".into(), revs.into().into()]);
+        self.hg(&args)?;
+        Ok(())
+    }
+}
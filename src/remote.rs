@@ -0,0 +1,217 @@
+use crate::branch::repo_heads;
+use crate::changeset::{parse_json_changesets, Changeset, LOG_TEMPLATE};
+use crate::cmdserver::CommandOutput;
+use crate::config::expand_path;
+use crate::error::HgError;
+use crate::node::NodeId;
+use crate::revlog::Revision;
+use crate::revset::Revset;
+use crate::store::Store;
+use crate::MercurialRepository;
+use regex::Regex;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct PullResult {
+    pub changesets: Vec<NodeId>,
+    pub new_heads: Vec<NodeId>,
+    /// `update` was requested but left files with merge conflicts.
+    pub unresolved: bool,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PushResult {
+    Pushed {
+        changesets: usize,
+        new_heads: usize,
+    },
+    NothingToPush,
+    /// The destination refused the changesets, e.g. because they would
+    /// create a new head or branch, or because a hook failed.
+    Rejected {
+        reason: String,
+    },
+}
+
+fn added_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"(?m)^(?:remote: )?added (\d+) changesets?.*?(?:\(\+(\d+) heads?\))?$").unwrap()
+    })
+}
+
+fn parse_push(output: &CommandOutput) -> Result<PushResult, HgError> {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match output.code {
+        0 => {
+            let (mut changesets, mut new_heads) = (0, 0);
+            for captures in added_pattern().captures_iter(&stdout) {
+                changesets += captures[1].parse::<usize>().unwrap_or(0);
+                new_heads += captures
+                    .get(2)
+                    .map_or(0, |heads| heads.as_str().parse().unwrap_or(0));
+            }
+            Ok(PushResult::Pushed {
+                changesets,
+                new_heads,
+            })
+        }
+        1 => Ok(PushResult::NothingToPush),
+        code => {
+            let reason = stderr
+                .find("abort: ")
+                .map(|start| stderr[start + "abort: ".len()..].trim_end());
+            match reason {
+                Some(reason) if reason.starts_with("push ") || reason.contains("hook") => {
+                    Ok(PushResult::Rejected {
+                        reason: reason.to_string(),
+                    })
+                }
+                _ => Err(HgError::CommandFailed {
+                    code: Some(code),
+                    stderr: stderr.into_owned(),
+                }),
+            }
+        }
+    }
+}
+
+fn changelog_heads(root: &Path) -> Result<(Revision, HashSet<Revision>), HgError> {
+    let changelog = Store::open(root)?.changelog()?;
+    Ok((changelog.tip(), repo_heads(&changelog)?))
+}
+
+impl MercurialRepository {
+    /// Resolves a name from `[paths]`, falling back to `default` (or
+    /// `default-push` when pushing). Names that are not configured are
+    /// returned unchanged so URLs and local paths can be passed directly.
+    pub fn remote_path(&self, name: Option<&str>, push: bool) -> Result<OsString, HgError> {
+        let config = self.config()?;
+        let mut candidates = vec![];
+        match name {
+            Some(name) if push => candidates.extend([format!("{}:pushurl", name), name.into()]),
+            Some(name) => candidates.push(name.to_string()),
+            None if push => {
+                candidates.extend(["default-push", "default:pushurl", "default"].map(String::from))
+            }
+            None => candidates.push("default".into()),
+        }
+        let value = candidates
+            .iter()
+            .find_map(|key| config.get("paths", key).filter(|v| !v.value.is_empty()));
+        let Some(value) = value else {
+            return Ok(name.unwrap_or("default").into());
+        };
+        if value.value.contains("://") {
+            return Ok(value.value.clone().into());
+        }
+        // Like hg, relative paths in the repository's hgrc are relative to its root.
+        let path = expand_path(&value.value);
+        if path.is_relative() && value.source.starts_with(self.path.join(".hg")) {
+            return Ok(self.path.join(path).into_os_string());
+        }
+        Ok(path.into_os_string())
+    }
+
+    pub fn pull(
+        &mut self,
+        source: Option<&str>,
+        revs: &[&str],
+        update: bool,
+    ) -> Result<PullResult, HgError> {
+        let mut args: Vec<OsString> = vec!["pull".into(), "-q".into()];
+        for rev in revs {
+            args.extend(["-r".into(), rev.into()]);
+        }
+        if update {
+            args.push("-u".into());
+        }
+        args.push(self.remote_path(source, false)?);
+
+        let (old_tip, old_heads) = changelog_heads(&self.path)?;
+        let output = self.hg_output(&args)?;
+        let unresolved = match output.code {
+            0 => false,
+            1 if update => true,
+            code => {
+                return Err(HgError::CommandFailed {
+                    code: Some(code),
+                    stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                })
+            }
+        };
+        if update {
+            self.try_update_statuses()?;
+        }
+
+        let changelog = Store::open(&self.path)?.changelog()?;
+        let changesets = (old_tip + 1..=changelog.tip())
+            .map(|rev| changelog.node(rev))
+            .collect::<Result<_, _>>()?;
+        let mut new_heads: Vec<Revision> = repo_heads(&changelog)?
+            .difference(&old_heads)
+            .copied()
+            .collect();
+        new_heads.sort();
+        Ok(PullResult {
+            changesets,
+            new_heads: new_heads
+                .into_iter()
+                .map(|rev| changelog.node(rev))
+                .collect::<Result<_, _>>()?,
+            unresolved,
+        })
+    }
+
+    pub fn push(
+        &self,
+        dest: Option<&str>,
+        revs: Option<Revset>,
+        force: bool,
+        new_branch: bool,
+    ) -> Result<PushResult, HgError> {
+        let mut args: Vec<OsString> = vec!["push".into()];
+        if let Some(revs) = revs {
+            args.extend(["-r".into(), revs.into()]);
+        }
+        if force {
+            args.push("--force".into());
+        }
+        if new_branch {
+            args.push("--new-branch".into());
+        }
+        args.push(self.remote_path(dest, true)?);
+        parse_push(&self.hg_output(&args)?)
+    }
+
+    pub fn incoming(&self, source: Option<&str>) -> Result<Vec<Changeset>, HgError> {
+        self.compare_remote("incoming", self.remote_path(source, false)?)
+    }
+
+    pub fn outgoing(&self, dest: Option<&str>) -> Result<Vec<Changeset>, HgError> {
+        self.compare_remote("outgoing", self.remote_path(dest, true)?)
+    }
+
+    fn compare_remote(&self, command: &str, path: OsString) -> Result<Vec<Changeset>, HgError> {
+        let args: Vec<OsString> = vec![
+            command.into(),
+            "-q".into(),
+            "-T".into(),
+            LOG_TEMPLATE.into(),
+            path,
+        ];
+        let output = self.hg_output(&args)?;
+        match output.code {
+            0 => parse_json_changesets(&output.stdout),
+            1 => Ok(vec![]),
+            code => Err(HgError::CommandFailed {
+                code: Some(code),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            }),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use crate::error::HgError;
+use crate::node::NodeId;
+use flate2::read::ZlibDecoder;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+pub type Revision = i32;
+
+pub const NULL_REVISION: Revision = -1;
+
+const INDEX_ENTRY_LEN: usize = 64;
+const REVLOGV1: u16 = 1;
+const FLAG_INLINE_DATA: u16 = 1 << 0;
+const FLAG_GENERALDELTA: u16 = 1 << 1;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct IndexEntry {
+    pub offset: u64,
+    pub flags: u16,
+    pub compressed_len: u32,
+    pub uncompressed_len: i32,
+    pub base: Revision,
+    pub linkrev: Revision,
+    pub p1: Revision,
+    pub p2: Revision,
+    pub node: NodeId,
+}
+
+#[derive(Debug, Clone)]
+pub struct Revlog {
+    index_path: PathBuf,
+    data_path: PathBuf,
+    index: Vec<u8>,
+    entries: Vec<IndexEntry>,
+    inline: bool,
+    generaldelta: bool,
+}
+
+impl Revlog {
+    pub fn open(index_path: &Path) -> Result<Revlog, HgError> {
+        let index = match fs::read(index_path) {
+            Ok(index) => index,
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        let mut revlog = Revlog {
+            index_path: index_path.to_path_buf(),
+            data_path: index_path.with_extension("d"),
+            index: vec![],
+            entries: vec![],
+            inline: false,
+            generaldelta: false,
+        };
+        if index.is_empty() {
+            return Ok(revlog);
+        }
+        let header = revlog.be_u32(&index, 0)?;
+        let (flags, version) = ((header >> 16) as u16, header as u16);
+        if version != REVLOGV1 {
+            return Err(revlog.corrupt(format!("unsupported revlog version {}", version)));
+        }
+        revlog.inline = flags & FLAG_INLINE_DATA != 0;
+        revlog.generaldelta = flags & FLAG_GENERALDELTA != 0;
+
+        let mut pos = 0;
+        while pos < index.len() {
+            let raw = index
+                .get(pos..pos + INDEX_ENTRY_LEN)
+                .ok_or_else(|| revlog.corrupt("truncated index entry".to_string()))?;
+            let offset_flags = u64::from_be_bytes(raw[0..8].try_into().unwrap());
+            let entry = IndexEntry {
+                offset: if revlog.entries.is_empty() {
+                    0
+                } else {
+                    offset_flags >> 16
+                },
+                flags: offset_flags as u16,
+                compressed_len: revlog.be_u32(raw, 8)?,
+                uncompressed_len: revlog.be_u32(raw, 12)? as i32,
+                base: revlog.be_u32(raw, 16)? as i32,
+                linkrev: revlog.be_u32(raw, 20)? as i32,
+                p1: revlog.be_u32(raw, 24)? as i32,
+                p2: revlog.be_u32(raw, 28)? as i32,
+                node: NodeId::from_bytes(&raw[32..52]).unwrap_or_default(),
+            };
+            pos += INDEX_ENTRY_LEN;
+            if revlog.inline {
+                pos += entry.compressed_len as usize;
+            }
+            revlog.entries.push(entry);
+        }
+        if revlog.inline {
+            revlog.index = index;
+        }
+        Ok(revlog)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn tip(&self) -> Revision {
+        self.entries.len() as Revision - 1
+    }
+
+    pub fn entry(&self, rev: Revision) -> Result<&IndexEntry, HgError> {
+        usize::try_from(rev)
+            .ok()
+            .and_then(|r| self.entries.get(r))
+            .ok_or_else(|| self.corrupt(format!("unknown revision {}", rev)))
+    }
+
+    pub fn node(&self, rev: Revision) -> Result<NodeId, HgError> {
+        if rev == NULL_REVISION {
+            return Ok(NodeId::NULL);
+        }
+        Ok(self.entry(rev)?.node)
+    }
+
+    pub fn parents(&self, rev: Revision) -> Result<[Revision; 2], HgError> {
+        let entry = self.entry(rev)?;
+        Ok([entry.p1, entry.p2])
+    }
+
+    pub fn rev(&self, node: &NodeId) -> Option<Revision> {
+        if node.is_null() {
+            return Some(NULL_REVISION);
+        }
+        self.entries
+            .iter()
+            .rposition(|e| e.node == *node)
+            .map(|r| r as Revision)
+    }
+
+    pub fn revision_by_node(&self, node: &NodeId) -> Result<Vec<u8>, HgError> {
+        let rev = self
+            .rev(node)
+            .ok_or_else(|| self.corrupt(format!("unknown node {}", node)))?;
+        self.revision(rev)
+    }
+
+    pub fn revision(&self, rev: Revision) -> Result<Vec<u8>, HgError> {
+        if rev == NULL_REVISION {
+            return Ok(vec![]);
+        }
+        let mut chain = vec![];
+        let mut current = rev;
+        loop {
+            let entry = self.entry(current)?;
+            chain.push(current);
+            if entry.base == current {
+                break;
+            }
+            let next = match self.generaldelta {
+                true => entry.base,
+                false => current - 1,
+            };
+            if next < 0 || next >= current {
+                return Err(self.corrupt(format!("invalid delta chain for {}", rev)));
+            }
+            current = next;
+        }
+
+        let mut data_file = match self.inline {
+            true => None,
+            false => Some(File::open(&self.data_path)?),
+        };
+        let base = chain.pop().unwrap();
+        let mut text = self.chunk(base, &mut data_file)?;
+        while let Some(delta_rev) = chain.pop() {
+            let delta = self.chunk(delta_rev, &mut data_file)?;
+            text = self.apply_delta(&text, &delta)?;
+        }
+        let expected = self.entry(rev)?.uncompressed_len;
+        if expected >= 0 && text.len() != expected as usize {
+            return Err(self.corrupt(format!(
+                "revision {} has length {}, expected {}",
+                rev,
+                text.len(),
+                expected
+            )));
+        }
+        Ok(text)
+    }
+
+    fn chunk(&self, rev: Revision, data_file: &mut Option<File>) -> Result<Vec<u8>, HgError> {
+        let entry = self.entry(rev)?;
+        let len = entry.compressed_len as usize;
+        let raw = match data_file {
+            None => {
+                let start = entry.offset as usize + (rev as usize + 1) * INDEX_ENTRY_LEN;
+                self.index
+                    .get(start..start + len)
+                    .ok_or_else(|| self.corrupt(format!("truncated data for {}", rev)))?
+                    .to_vec()
+            }
+            Some(file) => {
+                let mut raw = vec![0; len];
+                file.seek(SeekFrom::Start(entry.offset))?;
+                file.read_exact(&mut raw)?;
+                raw
+            }
+        };
+        self.decompress(raw)
+    }
+
+    fn decompress(&self, raw: Vec<u8>) -> Result<Vec<u8>, HgError> {
+        let mut out = vec![];
+        match raw.first() {
+            None | Some(b'\0') => return Ok(raw),
+            Some(b'u') => return Ok(raw[1..].to_vec()),
+            Some(b'x') => {
+                ZlibDecoder::new(raw.as_slice())
+                    .read_to_end(&mut out)
+                    .map_err(|e| self.corrupt(format!("invalid zlib chunk: {}", e)))?;
+            }
+            Some(b'(') => {
+                ruzstd::decoding::StreamingDecoder::new(raw.as_slice())
+                    .map_err(|e| self.corrupt(format!("invalid zstd chunk: {}", e)))?
+                    .read_to_end(&mut out)
+                    .map_err(|e| self.corrupt(format!("invalid zstd chunk: {}", e)))?;
+            }
+            Some(t) => {
+                return Err(self.corrupt(format!("unknown compression type {:?}", *t as char)))
+            }
+        }
+        Ok(out)
+    }
+
+    fn apply_delta(&self, base: &[u8], delta: &[u8]) -> Result<Vec<u8>, HgError> {
+        let mut out = Vec::with_capacity(base.len());
+        let mut last = 0;
+        let mut pos = 0;
+        while pos < delta.len() {
+            let start = self.be_u32(delta, pos)? as usize;
+            let end = self.be_u32(delta, pos + 4)? as usize;
+            let len = self.be_u32(delta, pos + 8)? as usize;
+            pos += 12;
+            let data = delta
+                .get(pos..pos + len)
+                .ok_or_else(|| self.corrupt("truncated delta".to_string()))?;
+            if start < last || end < start || end > base.len() {
+                return Err(self.corrupt("invalid delta hunk".to_string()));
+            }
+            out.extend_from_slice(&base[last..start]);
+            out.extend_from_slice(data);
+            last = end;
+            pos += len;
+        }
+        out.extend_from_slice(&base[last..]);
+        Ok(out)
+    }
+
+    fn be_u32(&self, data: &[u8], offset: usize) -> Result<u32, HgError> {
+        data.get(offset..offset + 4)
+            .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+            .ok_or_else(|| self.corrupt("unexpected end of data".to_string()))
+    }
+
+    fn corrupt(&self, reason: String) -> HgError {
+        HgError::Corrupt {
+            path: self.index_path.clone(),
+            reason,
+        }
+    }
+}
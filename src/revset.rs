@@ -0,0 +1,204 @@
+use crate::node::NodeId;
+use crate::revlog::Revision;
+use std::ffi::OsString;
+use std::fmt;
+use std::ops::{BitAnd, BitOr, Not, Sub};
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Revset {
+    expr: String,
+    atomic: bool,
+}
+
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_ascii_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl Revset {
+    /// Wraps an already formatted revset expression without any quoting.
+    pub fn raw(expr: &str) -> Revset {
+        let atomic = !expr.is_empty()
+            && expr
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_');
+        Revset {
+            expr: expr.to_string(),
+            atomic,
+        }
+    }
+
+    /// A revision identifier such as a tag, bookmark or branch name, quoted so
+    /// that names containing spaces or operators are not parsed as expressions.
+    pub fn symbol(name: &str) -> Revset {
+        Revset::atom(quote(name))
+    }
+
+    pub fn all() -> Revset {
+        Revset::function("all", &[])
+    }
+
+    pub fn ancestors(of: impl Into<Revset>) -> Revset {
+        Revset::function("ancestors", &[of.into().expr])
+    }
+
+    pub fn descendants(of: impl Into<Revset>) -> Revset {
+        Revset::function("descendants", &[of.into().expr])
+    }
+
+    pub fn parents(of: impl Into<Revset>) -> Revset {
+        Revset::function("parents", &[of.into().expr])
+    }
+
+    pub fn heads(of: impl Into<Revset>) -> Revset {
+        Revset::function("heads", &[of.into().expr])
+    }
+
+    pub fn branch(name: &str) -> Revset {
+        Revset::function("branch", &[quote(name)])
+    }
+
+    pub fn bookmark(name: &str) -> Revset {
+        Revset::function("bookmark", &[quote(name)])
+    }
+
+    pub fn tag(name: &str) -> Revset {
+        Revset::function("tag", &[quote(name)])
+    }
+
+    pub fn author(pattern: &str) -> Revset {
+        Revset::function("author", &[quote(pattern)])
+    }
+
+    pub fn keyword(text: &str) -> Revset {
+        Revset::function("keyword", &[quote(text)])
+    }
+
+    pub fn file(pattern: &str) -> Revset {
+        Revset::function("file", &[quote(pattern)])
+    }
+
+    pub fn date(range: &str) -> Revset {
+        Revset::function("date", &[quote(range)])
+    }
+
+    pub fn limit(self, count: usize) -> Revset {
+        Revset::function("limit", &[self.expr, count.to_string()])
+    }
+
+    pub fn dag_range(self, to: impl Into<Revset>) -> Revset {
+        self.binary("::", to.into())
+    }
+
+    fn atom(expr: String) -> Revset {
+        Revset { expr, atomic: true }
+    }
+
+    fn function(name: &str, args: &[String]) -> Revset {
+        Revset::atom(format!("{}({})", name, args.join(", ")))
+    }
+
+    fn operand(&self) -> String {
+        match self.atomic {
+            true => self.expr.clone(),
+            false => format!("({})", self.expr),
+        }
+    }
+
+    fn binary(self, op: &str, other: Revset) -> Revset {
+        Revset {
+            expr: format!("{} {} {}", self.operand(), op, other.operand()),
+            atomic: false,
+        }
+    }
+}
+
+impl fmt::Display for Revset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expr)
+    }
+}
+
+impl From<&str> for Revset {
+    fn from(expr: &str) -> Revset {
+        Revset::raw(expr)
+    }
+}
+
+impl From<String> for Revset {
+    fn from(expr: String) -> Revset {
+        Revset::raw(&expr)
+    }
+}
+
+impl From<&Revset> for Revset {
+    fn from(revset: &Revset) -> Revset {
+        revset.clone()
+    }
+}
+
+impl From<Revision> for Revset {
+    fn from(rev: Revision) -> Revset {
+        Revset::function("rev", &[rev.to_string()])
+    }
+}
+
+impl From<NodeId> for Revset {
+    fn from(node: NodeId) -> Revset {
+        Revset::atom(node.to_string())
+    }
+}
+
+impl From<Revset> for OsString {
+    fn from(revset: Revset) -> OsString {
+        revset.expr.into()
+    }
+}
+
+impl<R: Into<Revset>> BitAnd<R> for Revset {
+    type Output = Revset;
+
+    fn bitand(self, other: R) -> Revset {
+        self.binary("and", other.into())
+    }
+}
+
+impl<R: Into<Revset>> BitOr<R> for Revset {
+    type Output = Revset;
+
+    fn bitor(self, other: R) -> Revset {
+        self.binary("or", other.into())
+    }
+}
+
+impl<R: Into<Revset>> Sub<R> for Revset {
+    type Output = Revset;
+
+    fn sub(self, other: R) -> Revset {
+        self.binary("-", other.into())
+    }
+}
+
+impl Not for Revset {
+    type Output = Revset;
+
+    fn not(self) -> Revset {
+        Revset {
+            expr: format!("not {}", self.operand()),
+            atomic: false,
+        }
+    }
+}
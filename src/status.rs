@@ -0,0 +1,250 @@
+use crate::dirstate::{Dirstate, DirstateEntry, EntryState, SIZE_FROM_OTHER_PARENT};
+use crate::error::HgError;
+use crate::ignore::IgnoreMatcher;
+use crate::mercurial_file::{classify_copies, path_from_bytes, FileStatus, MercurialFile};
+use crate::node::NodeId;
+use crate::revset::Revset;
+use crate::store::{manifest_lines, strip_filelog_metadata, Store};
+use crate::{split_rows, MercurialRepository};
+use log::debug;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const RANGE_MASK: u64 = 0x7fff_ffff;
+
+pub fn native_status(root: &Path) -> Result<Vec<MercurialFile>, HgError> {
+    native_status_with(root, &IgnoreMatcher::from_repo(root)?)
+}
+
+pub(crate) fn native_status_with(
+    root: &Path,
+    ignore: &IgnoreMatcher,
+) -> Result<Vec<MercurialFile>, HgError> {
+    let dirstate = Dirstate::read(root)?;
+    let mut on_disk = BTreeSet::new();
+    walk(root, Path::new(""), &mut on_disk)?;
+
+    let mut files = vec![];
+    let mut unsure = vec![];
+    for (path, entry) in &dirstate.entries {
+        on_disk.remove(path);
+        let status = match entry.state {
+            EntryState::Removed => Some(FileStatus::Removed),
+            _ => match fs::symlink_metadata(root.join(path)) {
+                Ok(meta) if !meta.is_dir() => compare(entry, &meta),
+                _ => Some(FileStatus::Missing),
+            },
+        };
+        match status {
+            Some(status) => files.push(MercurialFile {
+                path: path.clone(),
+                status,
+                copied_from: match status {
+                    FileStatus::Added | FileStatus::Modified => entry.copy_source.clone(),
+                    _ => None,
+                },
+            }),
+            None => unsure.push(path.clone()),
+        }
+    }
+    if !unsure.is_empty() {
+        files.extend(resolve_unsure(root, &dirstate.parents[0], unsure));
+    }
+    files.extend(on_disk.into_iter().map(|path| MercurialFile {
+        status: match ignore.is_ignored(&path) {
+            true => FileStatus::Ignored,
+            false => FileStatus::NotTracked,
+        },
+        path,
+        copied_from: None,
+    }));
+    classify_copies(&mut files);
+    files.sort_by(|a, b| {
+        status_order(a.status)
+            .cmp(&status_order(b.status))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    Ok(files)
+}
+
+fn status_order(status: FileStatus) -> u8 {
+    match status {
+        FileStatus::Modified => 0,
+        FileStatus::Added | FileStatus::Renamed | FileStatus::Copied => 1,
+        FileStatus::Removed => 2,
+        FileStatus::Missing => 3,
+        FileStatus::NotTracked => 4,
+        FileStatus::Ignored => 5,
+        FileStatus::Clean => 6,
+        FileStatus::Directory => 7,
+    }
+}
+
+fn walk(root: &Path, dir: &Path, found: &mut BTreeSet<PathBuf>) -> Result<(), HgError> {
+    for dir_entry in fs::read_dir(root.join(dir))? {
+        let dir_entry = dir_entry?;
+        let name = dir_entry.file_name();
+        if dir.as_os_str().is_empty() && name == ".hg" {
+            continue;
+        }
+        let relative = dir.join(&name);
+        if dir_entry.file_type()?.is_dir() {
+            if root.join(&relative).join(".hg").exists() {
+                debug!("skipping nested repository {}", relative.display());
+                continue;
+            }
+            walk(root, &relative, found)?;
+        } else {
+            found.insert(relative);
+        }
+    }
+    Ok(())
+}
+
+fn compare(entry: &DirstateEntry, meta: &Metadata) -> Option<FileStatus> {
+    match entry.state {
+        EntryState::Added => return Some(FileStatus::Added),
+        EntryState::Merged => return Some(FileStatus::Modified),
+        _ => {}
+    }
+    let size = meta.len();
+    let size_changed =
+        entry.size >= 0 && entry.size as u64 != size && entry.size as u64 != size & RANGE_MASK;
+    let type_changed = entry.size >= 0 && entry.is_symlink() != meta.file_type().is_symlink();
+    if size_changed
+        || type_changed
+        || exec_changed(entry, meta)
+        || entry.size == SIZE_FROM_OTHER_PARENT
+        || entry.copy_source.is_some()
+    {
+        return Some(FileStatus::Modified);
+    }
+    let (secs, nanos) = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| (d.as_secs() & RANGE_MASK, d.subsec_nanos()))
+        .unwrap_or_default();
+    let same_mtime = entry.mtime >= 0
+        && entry.mtime as u64 == secs
+        && (entry.mtime_nanos == 0 || nanos == 0 || entry.mtime_nanos == nanos);
+    match entry.size >= 0 && same_mtime {
+        true => Some(FileStatus::Clean),
+        false => None,
+    }
+}
+
+fn resolve_unsure(root: &Path, parent: &NodeId, unsure: Vec<PathBuf>) -> Vec<MercurialFile> {
+    let clean = clean_files(root, parent, &unsure).unwrap_or_else(|e| {
+        debug!("unable to read the parent manifest: {}", e);
+        HashSet::new()
+    });
+    unsure
+        .into_iter()
+        .map(|path| MercurialFile {
+            status: match clean.contains(&path) {
+                true => FileStatus::Clean,
+                false => FileStatus::Modified,
+            },
+            path,
+            copied_from: None,
+        })
+        .collect()
+}
+
+fn clean_files(
+    root: &Path,
+    parent: &NodeId,
+    unsure: &[PathBuf],
+) -> Result<HashSet<PathBuf>, HgError> {
+    let store = Store::open(root)?;
+    let text = store.manifest_text(parent)?;
+    let entries: HashMap<PathBuf, &[u8]> = manifest_lines(&text)
+        .map(|(path, value)| (path_from_bytes(path), value))
+        .collect();
+    let mut clean = HashSet::new();
+    for path in unsure {
+        match same_content(root, &store, path, entries.get(path)) {
+            Ok(true) => {
+                clean.insert(path.clone());
+            }
+            Ok(false) => {}
+            Err(e) => debug!("unable to compare {}: {}", path.display(), e),
+        }
+    }
+    Ok(clean)
+}
+
+fn same_content(
+    root: &Path,
+    store: &Store,
+    path: &Path,
+    manifest_value: Option<&&[u8]>,
+) -> Result<bool, HgError> {
+    let Some(value) = manifest_value else {
+        return Ok(false);
+    };
+    let value = String::from_utf8_lossy(value);
+    let (hex, flags) = value.split_at(value.len().min(40));
+    let stored = store.filelog(path)?.revision_by_node(&hex.parse()?)?;
+    let on_disk = match flags.contains('l') {
+        true => fs::read_link(root.join(path))?
+            .to_string_lossy()
+            .into_owned()
+            .into_bytes(),
+        false => fs::read(root.join(path))?,
+    };
+    Ok(strip_filelog_metadata(&stored) == on_disk.as_slice())
+}
+
+#[cfg(unix)]
+fn exec_changed(entry: &DirstateEntry, meta: &Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    entry.size >= 0 && entry.is_exec() != (meta.permissions().mode() & 0o100 != 0)
+}
+
+#[cfg(not(unix))]
+fn exec_changed(_entry: &DirstateEntry, _meta: &Metadata) -> bool {
+    false
+}
+
+impl MercurialRepository {
+    pub fn status_between(
+        &self,
+        rev_a: impl Into<Revset>,
+        rev_b: impl Into<Revset>,
+        filter: &[&str],
+    ) -> Result<Vec<MercurialFile>, HgError> {
+        let revs = vec![
+            "--rev".into(),
+            rev_a.into().into(),
+            "--rev".into(),
+            rev_b.into().into(),
+        ];
+        self.rev_status(revs, filter)
+    }
+
+    pub fn status_change(
+        &self,
+        rev: impl Into<Revset>,
+        filter: &[&str],
+    ) -> Result<Vec<MercurialFile>, HgError> {
+        self.rev_status(vec!["--change".into(), rev.into().into()], filter)
+    }
+
+    fn rev_status(
+        &self,
+        revs: Vec<OsString>,
+        filter: &[&str],
+    ) -> Result<Vec<MercurialFile>, HgError> {
+        let mut args: Vec<OsString> = vec!["status".into(), "--copies".into()];
+        args.extend(revs);
+        args.push("--".into());
+        args.extend(filter.iter().map(OsString::from));
+        let output = self.hg(&args)?;
+        MercurialFile::parse_status(&split_rows(&output)?)
+    }
+}
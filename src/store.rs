@@ -0,0 +1,278 @@
+use crate::error::HgError;
+use crate::node::NodeId;
+use crate::revlog::Revlog;
+use std::collections::HashSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+const MAX_STORE_PATH_LEN: usize = 120;
+const DIR_PREFIX_LEN: usize = 8;
+const MAX_SHORT_DIRS_LEN: usize = 8 * (DIR_PREFIX_LEN + 1) - 4;
+
+#[derive(Debug, Clone)]
+pub struct Store {
+    root: PathBuf,
+    requirements: HashSet<String>,
+}
+
+impl Store {
+    pub fn open(repo_root: &Path) -> Result<Store, HgError> {
+        let hg_dir = repo_root.join(".hg");
+        let mut requirements = read_requirements(&hg_dir.join("requires"))?;
+        let shared_dir = match fs::read_to_string(hg_dir.join("sharedpath")) {
+            Ok(shared) => hg_dir.join(shared.trim_end()),
+            Err(e) if e.kind() == ErrorKind::NotFound => hg_dir,
+            Err(e) => return Err(e.into()),
+        };
+        if requirements.contains("share-safe") {
+            requirements.extend(read_requirements(
+                &shared_dir.join("store").join("requires"),
+            )?);
+        }
+        let root = match requirements.contains("store") {
+            true => shared_dir.join("store"),
+            false => shared_dir,
+        };
+        Ok(Store { root, requirements })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn has_requirement(&self, name: &str) -> bool {
+        self.requirements.contains(name)
+    }
+
+    pub fn changelog(&self) -> Result<Revlog, HgError> {
+        Revlog::open(&self.root.join("00changelog.i"))
+    }
+
+    pub fn manifest(&self) -> Result<Revlog, HgError> {
+        Revlog::open(&self.root.join("00manifest.i"))
+    }
+
+    pub fn filelog(&self, path: &Path) -> Result<Revlog, HgError> {
+        let path = path.to_string_lossy().replace('\\', "/");
+        let index = format!("data/{}.i", path);
+        let encoded = if !self.has_requirement("store") {
+            index
+        } else if !self.has_requirement("fncache") {
+            encode_filename(&encode_dir(&index))
+        } else {
+            hybrid_encode(&index, self.has_requirement("dotencode"))
+        };
+        Revlog::open(&self.root.join(encoded))
+    }
+
+    pub fn manifest_text(&self, changeset: &NodeId) -> Result<Vec<u8>, HgError> {
+        if changeset.is_null() {
+            return Ok(vec![]);
+        }
+        let text = self.changelog()?.revision_by_node(changeset)?;
+        let manifest_node = text
+            .split(|b| *b == b'\n')
+            .next()
+            .and_then(|line| std::str::from_utf8(line).ok())
+            .unwrap_or_default()
+            .parse::<NodeId>()?;
+        self.manifest()?.revision_by_node(&manifest_node)
+    }
+}
+
+pub(crate) fn manifest_lines(text: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    text.split(|b| *b == b'\n').filter_map(|line| {
+        let nul = line.iter().position(|b| *b == 0)?;
+        Some((&line[..nul], &line[nul + 1..]))
+    })
+}
+
+pub(crate) fn strip_filelog_metadata(text: &[u8]) -> &[u8] {
+    if !text.starts_with(b"\x01\n") {
+        return text;
+    }
+    match text[2..].windows(2).position(|w| w == b"\x01\n") {
+        Some(end) => &text[end + 4..],
+        None => text,
+    }
+}
+
+fn read_requirements(path: &Path) -> Result<HashSet<String>, HgError> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(content.lines().map(|l| l.trim().to_string()).collect()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn encode_dir(path: &str) -> String {
+    path.replace(".hg/", ".hg.hg/")
+        .replace(".i/", ".i.hg/")
+        .replace(".d/", ".d.hg/")
+}
+
+fn is_reserved(b: u8) -> bool {
+    !(32..126).contains(&b) || b"\\:*?\"<>|".contains(&b)
+}
+
+fn encode_filename(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' => {
+                out.push('_');
+                out.push(b.to_ascii_lowercase() as char);
+            }
+            b'_' => out.push_str("__"),
+            _ if is_reserved(b) => out.push_str(&format!("~{:02x}", b)),
+            _ => out.push(b as char),
+        }
+    }
+    out
+}
+
+fn lower_encode(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' => out.push(b.to_ascii_lowercase() as char),
+            _ if is_reserved(b) => out.push_str(&format!("~{:02x}", b)),
+            _ => out.push(b as char),
+        }
+    }
+    out
+}
+
+fn aux_encode(parts: &mut [String], dotencode: bool) {
+    for part in parts.iter_mut().filter(|p| !p.is_empty()) {
+        let first = part.as_bytes()[0];
+        if dotencode && (first == b'.' || first == b' ') {
+            *part = format!("~{:02x}{}", first, &part[1..]);
+        } else {
+            let stem_len = part.find('.').unwrap_or(part.len());
+            let bytes = part.as_bytes();
+            let reserved = (stem_len == 3 && ["aux", "con", "prn", "nul"].contains(&&part[..3]))
+                || (stem_len == 4
+                    && (b'1'..=b'9').contains(&bytes[3])
+                    && ["com", "lpt"].contains(&&part[..3]));
+            if reserved {
+                *part = format!("{}~{:02x}{}", &part[..2], bytes[2], &part[3..]);
+            }
+        }
+        let last = *part.as_bytes().last().unwrap();
+        if last == b'.' || last == b' ' {
+            part.pop();
+            part.push_str(&format!("~{:02x}", last));
+        }
+    }
+}
+
+fn hybrid_encode(path: &str, dotencode: bool) -> String {
+    let path = encode_dir(path);
+    let mut parts: Vec<String> = encode_filename(&path)
+        .split('/')
+        .map(String::from)
+        .collect();
+    aux_encode(&mut parts, dotencode);
+    let encoded = parts.join("/");
+    if encoded.len() <= MAX_STORE_PATH_LEN {
+        return encoded;
+    }
+    hash_encode(&path, dotencode)
+}
+
+fn hash_encode(path: &str, dotencode: bool) -> String {
+    let digest: String = sha1(path.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let mut parts: Vec<String> = lower_encode(&path[5..])
+        .split('/')
+        .map(String::from)
+        .collect();
+    aux_encode(&mut parts, dotencode);
+    let basename = parts.pop().unwrap_or_default();
+    let ext = match basename.rfind('.') {
+        Some(i) if i > 0 => &basename[i..],
+        _ => "",
+    };
+
+    let mut short_dirs: Vec<String> = vec![];
+    let mut short_dirs_len = 0;
+    for part in &parts {
+        let mut dir: String = part.chars().take(DIR_PREFIX_LEN).collect();
+        if dir.ends_with('.') || dir.ends_with(' ') {
+            dir.pop();
+            dir.push('_');
+        }
+        let total = match short_dirs_len {
+            0 => dir.len(),
+            len => len + 1 + dir.len(),
+        };
+        if short_dirs_len > 0 && total > MAX_SHORT_DIRS_LEN {
+            break;
+        }
+        short_dirs.push(dir);
+        short_dirs_len = total;
+    }
+    let mut dirs = short_dirs.join("/");
+    if !dirs.is_empty() {
+        dirs.push('/');
+    }
+    let res = format!("dh/{}{}{}", dirs, digest, ext);
+    match MAX_STORE_PATH_LEN.checked_sub(res.len()) {
+        Some(space_left) if space_left > 0 => {
+            let filler: String = basename.chars().take(space_left).collect();
+            format!("dh/{}{}{}{}", dirs, filler, digest, ext)
+        }
+        _ => res,
+    }
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+    let mut out = [0; 20];
+    for (chunk, word) in out.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
@@ -0,0 +1,111 @@
+use crate::error::HgError;
+use crate::node::NodeId;
+use crate::revlog::Revision;
+use crate::revset::Revset;
+use crate::store::{strip_filelog_metadata, Store};
+use crate::MercurialRepository;
+use log::debug;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Tag {
+    pub name: String,
+    pub node: NodeId,
+    pub rev: Revision,
+    pub local: bool,
+}
+
+pub(crate) fn parse_node_lines(content: &str) -> Vec<(String, NodeId)> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let (node, name) = line.split_once(' ')?;
+            match node.parse() {
+                Ok(node) => Some((name.trim().to_string(), node)),
+                Err(_) => {
+                    debug!("skipping malformed tag line {:?}", line);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+impl MercurialRepository {
+    /// Global tags are replayed from every committed revision of `.hgtags`, so
+    /// later entries win and entries pointing to the null node remove the tag.
+    pub fn tags(&self) -> Result<Vec<Tag>, HgError> {
+        let store = Store::open(&self.path)?;
+        let filelog = store.filelog(Path::new(".hgtags"))?;
+        let mut nodes: BTreeMap<String, (NodeId, bool)> = BTreeMap::new();
+        for rev in 0..filelog.len() as Revision {
+            let text = filelog.revision(rev)?;
+            let content = String::from_utf8_lossy(strip_filelog_metadata(&text));
+            for (name, node) in parse_node_lines(&content) {
+                nodes.insert(name, (node, false));
+            }
+        }
+        let local = match fs::read_to_string(self.path.join(".hg").join("localtags")) {
+            Ok(content) => parse_node_lines(&content),
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        for (name, node) in local {
+            nodes.insert(name, (node, true));
+        }
+
+        let changelog = store.changelog()?;
+        let mut tags = vec![];
+        for (name, (node, local)) in nodes.into_iter().filter(|(_, (n, _))| !n.is_null()) {
+            let Some(rev) = changelog.rev(&node) else {
+                debug!("ignoring tag {} pointing to unknown node {}", name, node);
+                continue;
+            };
+            tags.push(Tag {
+                name,
+                node,
+                rev,
+                local,
+            });
+        }
+        Ok(tags)
+    }
+
+    pub fn tag(
+        &mut self,
+        name: &str,
+        rev: Option<Revset>,
+        local: bool,
+        message: Option<&str>,
+    ) -> Result<(), HgError> {
+        let mut args: Vec<OsString> = vec!["tag".into()];
+        if let Some(rev) = rev {
+            args.extend(["-r".into(), rev.into()]);
+        }
+        if let Some(message) = message {
+            args.extend(["-m".into(), message.into()]);
+        }
+        self.run_tag(args, name, local)
+    }
+
+    pub fn remove_tag(&mut self, name: &str, local: bool) -> Result<(), HgError> {
+        self.run_tag(vec!["tag".into(), "--remove".into()], name, local)
+    }
+
+    fn run_tag(&mut self, mut args: Vec<OsString>, name: &str, local: bool) -> Result<(), HgError> {
+        if local {
+            args.push("--local".into());
+        }
+        args.push(name.into());
+        self.hg(&args)?;
+        match local {
+            true => Ok(()),
+            false => self.try_update_statuses(),
+        }
+    }
+}
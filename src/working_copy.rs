@@ -0,0 +1,166 @@
+use crate::dirstate::Dirstate;
+use crate::error::HgError;
+use crate::mercurial_file::FileStatus;
+use crate::node::NodeId;
+use crate::revset::Revset;
+use crate::store::Store;
+use crate::MercurialRepository;
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct WorkingCopyInfo {
+    pub parents: Vec<NodeId>,
+    pub branch: String,
+    pub bookmark: Option<String>,
+    pub tags: Vec<String>,
+    pub merging: bool,
+    pub dirty: bool,
+}
+
+fn read_optional(path: &Path) -> Result<Option<String>, HgError> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn revert_args(rev: Option<Revset>, no_backup: bool) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec!["revert".into()];
+    if let Some(rev) = rev {
+        args.extend(["-r".into(), rev.into()]);
+    }
+    if no_backup {
+        args.push("--no-backup".into());
+    }
+    args
+}
+
+impl MercurialRepository {
+    pub(crate) fn working_copy_bookmark(&self) -> Result<Option<String>, HgError> {
+        Ok(
+            read_optional(&self.path.join(".hg").join("bookmarks.current"))?
+                .map(|b| b.trim().to_string())
+                .filter(|b| !b.is_empty()),
+        )
+    }
+
+    pub fn working_copy(&self) -> Result<WorkingCopyInfo, HgError> {
+        let hg_dir = self.path.join(".hg");
+        let dirstate = Dirstate::read(&self.path)?;
+        let parents: Vec<NodeId> = dirstate
+            .parents
+            .iter()
+            .filter(|p| !p.is_null())
+            .copied()
+            .collect();
+        let branch = read_optional(&hg_dir.join("branch"))?
+            .map(|b| b.trim().to_string())
+            .filter(|b| !b.is_empty())
+            .unwrap_or_else(|| "default".to_string());
+        let bookmark = self.working_copy_bookmark()?;
+
+        let mut tags: Vec<String> = self
+            .tags()?
+            .into_iter()
+            .filter(|tag| parents.first() == Some(&tag.node))
+            .map(|tag| tag.name)
+            .collect();
+        let changelog = Store::open(&self.path)?.changelog()?;
+        if !changelog.is_empty() && parents.first() == Some(&changelog.node(changelog.tip())?) {
+            tags.insert(0, "tip".to_string());
+        }
+
+        let (_, files) = self.read_statuses()?;
+        let dirty = files.iter().any(|f| {
+            matches!(
+                f.status,
+                FileStatus::Modified
+                    | FileStatus::Added
+                    | FileStatus::Removed
+                    | FileStatus::Missing
+                    | FileStatus::Renamed
+                    | FileStatus::Copied
+            )
+        });
+        Ok(WorkingCopyInfo {
+            merging: parents.len() > 1,
+            parents,
+            branch,
+            bookmark,
+            tags,
+            dirty,
+        })
+    }
+
+    pub fn add<P: AsRef<Path>>(&mut self, paths: &[P]) -> Result<(), HgError> {
+        self.mutate_paths(vec!["add".into()], paths)
+    }
+
+    pub fn remove<P: AsRef<Path>>(&mut self, paths: &[P]) -> Result<(), HgError> {
+        self.mutate_paths(vec!["remove".into()], paths)
+    }
+
+    pub fn forget<P: AsRef<Path>>(&mut self, paths: &[P]) -> Result<(), HgError> {
+        self.mutate_paths(vec!["forget".into()], paths)
+    }
+
+    pub fn addremove(&mut self, similarity: Option<u8>) -> Result<(), HgError> {
+        let mut args: Vec<OsString> = vec!["addremove".into()];
+        if let Some(similarity) = similarity {
+            args.extend(["-s".into(), similarity.to_string().into()]);
+        }
+        self.mutate::<&Path>(args, &[])
+    }
+
+    pub fn revert<P: AsRef<Path>>(
+        &mut self,
+        paths: &[P],
+        rev: Option<Revset>,
+        no_backup: bool,
+    ) -> Result<(), HgError> {
+        self.mutate_paths(revert_args(rev, no_backup), paths)
+    }
+
+    /// Reverts every file in the working copy, like `hg revert --all`.
+    pub fn revert_all(&mut self, rev: Option<Revset>, no_backup: bool) -> Result<(), HgError> {
+        let mut args = revert_args(rev, no_backup);
+        args.push("--all".into());
+        self.mutate::<&Path>(args, &[])
+    }
+
+    pub fn rename(&mut self, source: &Path, dest: &Path) -> Result<(), HgError> {
+        self.mutate(vec!["rename".into()], &[source, dest])
+    }
+
+    pub fn copy(&mut self, source: &Path, dest: &Path) -> Result<(), HgError> {
+        self.mutate(vec!["copy".into()], &[source, dest])
+    }
+
+    /// Like `mutate`, but refuses an empty list since hg would then act on
+    /// the whole working copy.
+    fn mutate_paths<P: AsRef<Path>>(
+        &mut self,
+        args: Vec<OsString>,
+        paths: &[P],
+    ) -> Result<(), HgError> {
+        if paths.is_empty() {
+            return Err(HgError::NoFiles);
+        }
+        self.mutate(args, paths)
+    }
+
+    fn mutate<P: AsRef<Path>>(
+        &mut self,
+        mut args: Vec<OsString>,
+        paths: &[P],
+    ) -> Result<(), HgError> {
+        args.push("--".into());
+        args.extend(paths.iter().map(|p| self.path_arg(p.as_ref())));
+        self.hg(&args)?;
+        self.try_update_statuses()
+    }
+}